@@ -0,0 +1,386 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{dense::Continuous, is_degenerate, Derivative, Point, Stepper};
+
+/// Error tolerances for adaptive integration. A step is accepted when the
+/// estimated local error is below `absolute + relative * |y|`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tolerance {
+    pub absolute: f64,
+    pub relative: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance {
+            absolute: 1e-6,
+            relative: 1e-6,
+        }
+    }
+}
+
+/// How many steps an adaptive integration accepted and rejected.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StepStats {
+    pub accepted: usize,
+    pub rejected: usize,
+}
+
+const C: [f64; 7] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
+
+const A: [[f64; 6]; 7] = [
+    [0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [
+        19372.0 / 6561.0,
+        -25360.0 / 2187.0,
+        64448.0 / 6561.0,
+        -212.0 / 729.0,
+        0.0,
+        0.0,
+    ],
+    [
+        9017.0 / 3168.0,
+        -355.0 / 33.0,
+        46732.0 / 5247.0,
+        49.0 / 176.0,
+        -5103.0 / 18656.0,
+        0.0,
+    ],
+    [
+        35.0 / 384.0,
+        0.0,
+        500.0 / 1113.0,
+        125.0 / 192.0,
+        -2187.0 / 6784.0,
+        11.0 / 84.0,
+    ],
+];
+
+// difference between the fifth and embedded fourth order weights
+const E: [f64; 7] = [
+    71.0 / 57600.0,
+    0.0,
+    -71.0 / 16695.0,
+    71.0 / 1920.0,
+    -17253.0 / 339200.0,
+    22.0 / 525.0,
+    -1.0 / 40.0,
+];
+
+// coefficients of the continuous extension: y(x + theta h) = y(x) +
+// h sum_i k_i sum_j P[i][j] theta^(j + 1), fourth order accurate
+const P: [[f64; 4]; 7] = [
+    [
+        1.0,
+        -8048581381.0 / 2820520608.0,
+        8663915743.0 / 2820520608.0,
+        -12715105075.0 / 11282082432.0,
+    ],
+    [0.0, 0.0, 0.0, 0.0],
+    [
+        0.0,
+        131558114200.0 / 32700410799.0,
+        -68118460800.0 / 10900136933.0,
+        87487479700.0 / 32700410799.0,
+    ],
+    [
+        0.0,
+        -1754552775.0 / 470086768.0,
+        14199869525.0 / 1410260304.0,
+        -10690763975.0 / 1880347072.0,
+    ],
+    [
+        0.0,
+        127303824393.0 / 49829197408.0,
+        -318862633887.0 / 49829197408.0,
+        701980252875.0 / 199316789632.0,
+    ],
+    [
+        0.0,
+        -282668133.0 / 205662961.0,
+        2019193451.0 / 616988883.0,
+        -1453857185.0 / 822651844.0,
+    ],
+    [
+        0.0,
+        40617522.0 / 29380423.0,
+        -110615467.0 / 29380423.0,
+        69997945.0 / 29380423.0,
+    ],
+];
+
+const SAFETY: f64 = 0.9;
+const MIN_FACTOR: f64 = 0.2;
+const MAX_FACTOR: f64 = 5.0;
+
+/// The Dormand–Prince 5(4) embedded pair. Each call to [`Stepper::step`]
+/// takes one accepted step, growing or shrinking the step size so the local
+/// error stays within `tolerance`. The `step_size` passed in is only used as
+/// the initial guess.
+#[derive(Debug, Clone)]
+pub struct DormandPrince {
+    pub tolerance: Tolerance,
+    stats: StepStats,
+    step_size: Option<f64>,
+    k: [Vec<f64>; 7],
+    next: Vec<f64>,
+    // the point `k[0]` was evaluated at, reused across steps
+    first_stage: Option<Point>,
+    // start and size of the last accepted step
+    last_step: Option<(f64, f64)>,
+}
+
+impl DormandPrince {
+    pub fn new(tolerance: Tolerance) -> Self {
+        DormandPrince {
+            tolerance,
+            stats: StepStats::default(),
+            step_size: None,
+            k: Default::default(),
+            next: vec![],
+            first_stage: None,
+            last_step: None,
+        }
+    }
+
+    pub fn stats(&self) -> StepStats {
+        self.stats
+    }
+
+    /// The root mean square of the local error estimate, scaled by the
+    /// tolerance of each component. A step is acceptable below 1.
+    fn error(&self, y: &[f64], h: f64) -> f64 {
+        let sum = (0..y.len())
+            .map(|i| {
+                let error = h * (0..7).map(|j| E[j] * self.k[j][i]).sum::<f64>();
+                let scale = self.tolerance.absolute
+                    + self.tolerance.relative * y[i].abs().max(self.next[i].abs());
+
+                (error / scale).powi(2)
+            })
+            .sum::<f64>();
+
+        (sum / y.len().max(1) as f64).sqrt()
+    }
+}
+
+impl Stepper for DormandPrince {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let n = current.y.len();
+        for k in &mut self.k {
+            k.resize(n, 0.0);
+        }
+
+        if self.first_stage.as_ref() != Some(current) {
+            derivative(current.x, &current.y, &mut self.k[0]);
+        }
+
+        let mut step_size = self.step_size.unwrap_or(step_size);
+
+        loop {
+            let h = step_size;
+
+            for stage in 1..7 {
+                self.next.clear();
+                self.next.extend_from_slice(&current.y);
+
+                for (&a, k) in A[stage].iter().zip(&self.k) {
+                    if a != 0.0 {
+                        for (next, k) in self.next.iter_mut().zip(k) {
+                            *next += h * a * k;
+                        }
+                    }
+                }
+
+                derivative(current.x + C[stage] * h, &self.next, &mut self.k[stage]);
+            }
+
+            // the last stage is evaluated at the fifth order solution, which
+            // is left in `next`
+            let error = self.error(&current.y, h);
+            let min_step = f64::EPSILON * current.x.abs().max(1.0) * 16.0;
+
+            if error <= 1.0 || is_degenerate(error) || h.abs() <= min_step {
+                let factor = if error == 0.0 {
+                    MAX_FACTOR
+                } else {
+                    (SAFETY * error.powf(-0.2)).clamp(MIN_FACTOR, MAX_FACTOR)
+                };
+
+                std::mem::swap(&mut current.y, &mut self.next);
+                self.last_step = Some((current.x, h));
+                current.x += h;
+                self.step_size = Some(h * factor);
+                self.stats.accepted += 1;
+
+                // first same as last: the final stage is the next first stage
+                self.k.swap(0, 6);
+                self.first_stage = Some(current.clone());
+                return;
+            }
+
+            step_size = h * (SAFETY * error.powf(-0.2)).max(MIN_FACTOR);
+            self.stats.rejected += 1;
+        }
+    }
+
+    fn continuous(&self) -> Option<Continuous> {
+        let (x, step) = self.last_step?;
+
+        // the first stage of the next step is the last of this one
+        let stage = |i: usize| match i {
+            0 => &self.k[6],
+            6 => &self.k[0],
+            i => &self.k[i],
+        };
+        let coefficients = (0..self.k[0].len())
+            .map(|component| {
+                let mut coefficients = [0.0; 4];
+                for (i, row) in P.iter().enumerate() {
+                    let k = stage(i)[component];
+                    for (coefficient, p) in coefficients.iter_mut().zip(row) {
+                        *coefficient += p * k;
+                    }
+                }
+                coefficients
+            })
+            .collect();
+
+        Some(Continuous {
+            x,
+            step,
+            coefficients,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_dataset, scalar, EndCondition, Euler};
+
+    #[test]
+    fn meets_tolerance() {
+        let mut stepper = DormandPrince::new(Tolerance {
+            absolute: 1e-9,
+            relative: 1e-9,
+        });
+        let points = create_dataset(
+            (0.0, 1.0).into(),
+            0.1,
+            &mut stepper,
+            EndCondition::until(5.0),
+            scalar(|_, y| y),
+        );
+
+        let worst = points
+            .iter()
+            .map(|p| ((p.y[0] - p.x.exp()) / p.x.exp()).abs())
+            .fold(0.0, f64::max);
+
+        assert!(worst < 1e-7, "relative error {worst}");
+        // every accepted step adds a point, the last one cut short at x = 5
+        assert_eq!(stepper.stats().accepted + 1, points.len());
+    }
+
+    #[test]
+    fn fewer_steps_than_fixed() {
+        let f = scalar(|x, y| y.cbrt() + x);
+        let fixed = create_dataset(
+            (0.0, 10.0).into(),
+            0.001,
+            &mut Euler::default(),
+            EndCondition::until(5.0),
+            &f,
+        );
+
+        let mut stepper = DormandPrince::new(Tolerance::default());
+        let adaptive = create_dataset(
+            (0.0, 10.0).into(),
+            0.001,
+            &mut stepper,
+            EndCondition::until(5.0),
+            &f,
+        );
+        let stats = stepper.stats();
+
+        assert!(adaptive.len() * 50 < fixed.len());
+        assert!(stats.accepted + stats.rejected < fixed.len() / 50);
+    }
+
+    #[test]
+    fn lorenz_system_within_tolerance() {
+        let lorenz = |_: f64, y: &[f64], dy: &mut [f64]| {
+            dy[0] = 10.0 * (y[1] - y[0]);
+            dy[1] = y[0] * (28.0 - y[2]) - y[1];
+            dy[2] = y[0] * y[1] - 8.0 / 3.0 * y[2];
+        };
+        let start: Point = (0.0, [1.0, 1.0, 1.0]).into();
+
+        let mut stepper = DormandPrince::new(Tolerance {
+            absolute: 1e-10,
+            relative: 1e-10,
+        });
+        let adaptive = create_dataset(
+            start.clone(),
+            0.01,
+            &mut stepper,
+            EndCondition::until(1.0),
+            lorenz,
+        );
+        let reference = create_dataset(
+            start,
+            1e-4,
+            &mut crate::RungeKutta4::default(),
+            EndCondition::until(adaptive.last().unwrap().x - 1e-9),
+            lorenz,
+        );
+
+        let last = adaptive.last().unwrap();
+        let mut check = reference.last().unwrap().clone();
+        let remaining = last.x - check.x;
+        crate::RungeKutta4::default().step(&lorenz, &mut check, remaining);
+
+        for (a, b) in last.y.iter().zip(&check.y) {
+            assert!((a - b).abs() < 1e-6, "{a} vs {b}");
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_initial_step() {
+        let mut stepper = DormandPrince::new(Tolerance::default());
+        create_dataset(
+            (0.0, 1.0).into(),
+            10.0,
+            &mut stepper,
+            EndCondition::until(1.0),
+            scalar(|x, y| -50.0 * (y - x.cos())),
+        );
+
+        assert!(stepper.stats().rejected > 0);
+    }
+
+    #[test]
+    fn respects_end_condition() {
+        let end_condition = EndCondition {
+            max_x: None,
+            min_x: None,
+            max_abs_y: Some(100.0),
+        };
+        let mut stepper = DormandPrince::new(Tolerance::default());
+        let points = create_dataset(
+            (0.0, 1.0).into(),
+            0.1,
+            &mut stepper,
+            end_condition,
+            scalar(|_, y| y),
+        );
+
+        assert!(points.iter().all(|p| p.y[0].abs() <= 100.0));
+        assert!((points.last().unwrap().y[0] - 100.0).abs() < 1e-4);
+    }
+}
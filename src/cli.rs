@@ -0,0 +1,882 @@
+use std::path::PathBuf;
+
+use clap::{
+    builder::PossibleValuesParser, error::ErrorKind, Args, CommandFactory, Parser, Subcommand,
+    ValueEnum,
+};
+
+use differential::{
+    event::{Action, Direction},
+    pde::{Boundaries, Boundary},
+    plot::Backend,
+    scenario::{
+        Axis, Combine, DelaySettings, EnsembleBands, EventSettings, InitialConditions, Limits,
+        NoiseSettings, PlotSettings, Scenario, SteadyStateSettings,
+    },
+    Method, Tolerance,
+};
+
+/// Numerically solves initial value problems y' = f(x, y) for a family of
+/// starting values and plots or exports the resulting trajectories.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Read the whole run from a TOML or JSON scenario file instead of the
+    /// options below.
+    #[arg(
+        short,
+        long,
+        global = true,
+        conflicts_with_all = [
+            "equations",
+            "method",
+            "step_size",
+            "bidirectional",
+            "jacobian",
+            "diffusion",
+            "seed",
+            "paths",
+            "delays",
+            "history",
+            "atol",
+            "rtol",
+            "start_x",
+            "start_y",
+            "y_spread",
+            "num_datasets",
+            "max_x",
+            "min_x",
+            "max_abs_y",
+            "y_box",
+            "max_steps",
+            "time_limit",
+            "steady_state",
+            "all_limits",
+            "events",
+            "resample",
+        ]
+    )]
+    pub scenario: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub run: RunArgs,
+
+    /// What to do with the trajectories, plotting them when omitted.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Integrate every trajectory and print a summary of where each ended.
+    Solve,
+    /// Integrate every trajectory and draw them onto one chart.
+    Plot(PlotArgs),
+    /// Integrate every trajectory and write the points as CSV or JSON.
+    Export(ExportArgs),
+    /// Locate the equilibria of an autonomous system over the region the
+    /// trajectories cover, and classify their stability.
+    Equilibria(EquilibriaArgs),
+    /// Solve a boundary value problem by shooting from the first initial
+    /// point, with some components given at the start x and the others at
+    /// --to. The limits on the trajectories do not apply.
+    Shoot(ShootArgs),
+    /// Solve u_t = D u_xx + f(x, u) on a grid by the method of lines, from
+    /// the start x and up to its limits as time, and draw u over space and
+    /// time. The equations and initial values do not apply.
+    Pde(PdeArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    /// Right-hand side of the equation in x and y. Repeat once per
+    /// component to solve a system in y0, y1, ...
+    #[arg(
+        short,
+        long = "equation",
+        global = true,
+        default_value = "cbrt(y) + x",
+        allow_hyphen_values = true
+    )]
+    pub equations: Vec<String>,
+
+    /// Integration scheme; rk45 is adaptive and controlled by --atol and
+    /// --rtol, which also bound the Newton iterations of the implicit
+    /// backward-euler, crank-nicolson and the variable-order bdf1 to bdf5,
+    /// named by their highest order.
+    #[arg(
+        short,
+        long,
+        global = true,
+        default_value = "euler",
+        value_parser = PossibleValuesParser::new(Method::NAMES)
+    )]
+    pub method: String,
+
+    /// Step size, or the initial step size for adaptive methods. A negative
+    /// step integrates towards decreasing x.
+    #[arg(
+        short = 'd',
+        long = "step",
+        global = true,
+        default_value_t = 0.001,
+        value_parser = nonzero,
+        allow_negative_numbers = true
+    )]
+    pub step_size: f64,
+
+    /// Integrate both ways from the initial point and join the two branches
+    /// into one curve.
+    #[arg(long, global = true)]
+    pub bidirectional: bool,
+
+    /// One row of the Jacobian for implicit methods, the derivatives of an
+    /// equation with respect to y0, y1, ... separated by `;`. Repeat once
+    /// per equation; estimated numerically when left out.
+    #[arg(long = "jacobian", global = true, value_parser = row, allow_hyphen_values = true)]
+    pub jacobian: Vec<Vec<String>>,
+
+    /// Diffusion of one equation for the stochastic euler-maruyama and
+    /// milstein, the factor of its own Wiener process. Repeat once per
+    /// equation.
+    #[arg(long, global = true, allow_hyphen_values = true)]
+    pub diffusion: Vec<String>,
+
+    /// Seed of the noise, so that a stochastic run can be repeated.
+    #[arg(long, global = true, default_value_t = 0, requires = "diffusion")]
+    pub seed: u64,
+
+    /// Sample paths to take from each initial point of a stochastic run.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 1,
+        requires = "diffusion",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub paths: u64,
+
+    /// Constant delay the equations may look back by, referring to y{i} at
+    /// x minus the j-th delay as y{i}_{j}. Repeat for several delays.
+    #[arg(long = "delay", global = true, value_parser = positive)]
+    pub delays: Vec<f64>,
+
+    /// State before the start of a delay equation, an expression in x per
+    /// equation. Each trajectory holds its initial value when left out.
+    #[arg(long, global = true, requires = "delays", allow_hyphen_values = true)]
+    pub history: Vec<String>,
+
+    /// Absolute error tolerance for adaptive and implicit methods.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 1e-6,
+        value_parser = non_negative,
+        allow_negative_numbers = true
+    )]
+    pub atol: f64,
+
+    /// Relative error tolerance for adaptive and implicit methods.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 1e-6,
+        value_parser = non_negative,
+        allow_negative_numbers = true
+    )]
+    pub rtol: f64,
+
+    /// Value of x every trajectory starts at.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 0.0,
+        allow_negative_numbers = true
+    )]
+    pub start_x: f64,
+
+    /// Initial value of each component of the first trajectory,
+    /// comma separated.
+    #[arg(
+        long,
+        global = true,
+        value_delimiter = ',',
+        default_value = "0",
+        allow_negative_numbers = true
+    )]
+    pub start_y: Vec<f64>,
+
+    /// Offset added to the initial value of y0 for each further trajectory.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 10.0,
+        allow_negative_numbers = true
+    )]
+    pub y_spread: f64,
+
+    /// Number of trajectories to integrate.
+    #[arg(
+        short = 'n',
+        long,
+        global = true,
+        default_value_t = 10,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub num_datasets: u32,
+
+    /// Stop once x exceeds this value [default: 150 past --start-x when
+    /// integrating forwards, unless --all-limits]
+    #[arg(long, global = true, allow_negative_numbers = true)]
+    pub max_x: Option<f64>,
+
+    /// Stop once any component of y exceeds this value in magnitude
+    /// [default: 150, unless --all-limits]
+    #[arg(
+        long,
+        global = true,
+        value_parser = positive,
+        allow_negative_numbers = true
+    )]
+    pub max_abs_y: Option<f64>,
+
+    /// Stop once x falls below this value [default: 150 before --start-x
+    /// when integrating backwards, unless --all-limits]
+    #[arg(long, global = true, allow_negative_numbers = true)]
+    pub min_x: Option<f64>,
+
+    /// Stop once a component of y leaves `LOWER,UPPER`. Given once per
+    /// component, in order.
+    #[arg(long, global = true, value_parser = bounds, allow_hyphen_values = true)]
+    pub y_box: Vec<[f64; 2]>,
+
+    /// Stop after this many steps.
+    #[arg(long, global = true, value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_steps: Option<u64>,
+
+    /// Stop a trajectory once integrating it has taken this many seconds.
+    #[arg(long, global = true, value_parser = positive)]
+    pub time_limit: Option<f64>,
+
+    /// Stop once every component of y' has stayed below EPSILON in
+    /// magnitude, as `EPSILON[:STEPS]`, for STEPS consecutive steps
+    /// [default: 10].
+    #[arg(long, global = true, value_parser = steady_state)]
+    pub steady_state: Option<SteadyStateSettings>,
+
+    /// Stop only once all of the limits given are reached at the same time,
+    /// instead of any of them. The default limits are left out.
+    #[arg(long, global = true)]
+    pub all_limits: bool,
+
+    /// Record where an expression in x and y crosses zero, as
+    /// `EXPR[:DIRECTION[:ACTION]]` with a direction of rising, falling or
+    /// both [default], and an action of record [default] or terminate. May
+    /// be repeated.
+    #[arg(long = "event", global = true, value_parser = event, allow_hyphen_values = true)]
+    pub events: Vec<EventSettings>,
+
+    /// Replace the points of every trajectory with its interpolated values
+    /// at COUNT evenly spaced x, as `FROM,TO,COUNT`.
+    #[arg(long, global = true, value_parser = grid, allow_hyphen_values = true)]
+    pub resample: Option<Axis>,
+}
+
+/// Overrides for the plot settings of the run. Defaults are given for runs
+/// described on the command line; a scenario file supplies its own.
+#[derive(Debug, Default, Args)]
+pub struct PlotArgs {
+    /// Image file to write [default: output.png]
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Width of the image in pixels [default: 1280]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: Option<u32>,
+
+    /// Height of the image in pixels [default: 960]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub height: Option<u32>,
+
+    /// Draw two components against each other, as `I,J`, instead of every
+    /// component against x. May be repeated.
+    #[arg(long = "pair", value_parser = pair)]
+    pub pairs: Vec<(usize, usize)>,
+
+    /// How to render the chart [default: svg for a `.svg` output, bitmap
+    /// otherwise]
+    #[arg(long, value_parser = PossibleValuesParser::new(Backend::NAMES))]
+    pub backend: Option<String>,
+
+    /// Draw the direction field of a scalar equation beneath the curves.
+    #[arg(long)]
+    pub direction_field: bool,
+
+    /// Color the direction field by the magnitude of the slope.
+    #[arg(long, requires = "direction_field")]
+    pub color_slopes: bool,
+
+    /// Draw the vector field and nullclines of a two-component system
+    /// beneath its trajectories in the phase plane, of components 0 and 1
+    /// unless --pair is given.
+    #[arg(long, conflicts_with = "direction_field")]
+    pub phase_portrait: bool,
+
+    /// Color the arrows of the phase portrait by the speed of the flow.
+    #[arg(long, requires = "phase_portrait")]
+    pub color_speed: bool,
+
+    /// Leave the nullclines out of the phase portrait.
+    #[arg(long, requires = "phase_portrait")]
+    pub no_nullclines: bool,
+
+    /// Mark the equilibria on the phase portrait.
+    #[arg(long, requires = "phase_portrait")]
+    pub equilibria: bool,
+
+    /// Chart how far a quantity that should be conserved, like the energy
+    /// `(y0^2 + y1^2)/2` of an oscillator, strays from its initial value
+    /// along each trajectory, instead of the trajectories themselves.
+    #[arg(
+        long,
+        allow_hyphen_values = true,
+        conflicts_with_all = ["pairs", "direction_field", "phase_portrait"]
+    )]
+    pub conserved: Option<String>,
+
+    /// Draw the mean of the sample paths from each initial point of a
+    /// stochastic run, within a band between two percentiles, as
+    /// `LOWER,UPPER` [default: 5,95]
+    #[arg(
+        long,
+        num_args = 0..=1,
+        default_missing_value = "5,95",
+        value_parser = bounds,
+        conflicts_with_all = ["pairs", "direction_field", "phase_portrait", "conserved"]
+    )]
+    pub bands: Option<[f64; 2]>,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// File to write, or `-` for standard output.
+    #[arg(short, long, default_value = "-")]
+    pub output: PathBuf,
+
+    /// Output format [default: json for a `.json` output, csv otherwise]
+    #[arg(short, long, value_enum)]
+    pub format: Option<ExportFormat>,
+
+    /// Write each trajectory to its own CSV file, named after the output
+    /// with the trajectory number appended, instead of one long table.
+    #[arg(long)]
+    pub per_trajectory: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct EquilibriaArgs {
+    /// Newton's method is started from a grid with this many points along
+    /// each component.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    pub seeds: u32,
+
+    /// Print the equilibria as JSON, with their Jacobians.
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ShootArgs {
+    /// The x at which the second set of conditions holds, beyond the start.
+    #[arg(long, allow_negative_numbers = true)]
+    pub to: f64,
+
+    /// A component given at the start, as `I=VALUE`; the others take their
+    /// initial values as a first guess. Repeat for several components.
+    #[arg(long, value_parser = condition, allow_hyphen_values = true)]
+    pub at_start: Vec<(usize, f64)>,
+
+    /// A component given at --to, as `I=VALUE`, one for each component not
+    /// given at the start.
+    #[arg(long, value_parser = condition, required = true, allow_hyphen_values = true)]
+    pub at_end: Vec<(usize, f64)>,
+
+    /// Split the interval into this many segments, each integrated from a
+    /// state of its own, for problems too sensitive to shoot across at once.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub segments: u32,
+
+    /// Largest mismatch with the conditions accepted as a solution.
+    #[arg(long, default_value_t = 1e-8, value_parser = positive)]
+    pub tolerance: f64,
+
+    /// Corrections to try before giving up.
+    #[arg(long, default_value_t = 50)]
+    pub max_iterations: usize,
+
+    /// Write the solution as CSV to this file, or `-` for standard output.
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct PdeArgs {
+    /// The grid, as `FROM,TO,POINTS`; a periodic domain leaves out TO,
+    /// which is the same point as FROM.
+    #[arg(long, default_value = "0,1,51", value_parser = grid, allow_hyphen_values = true)]
+    pub domain: Axis,
+
+    /// The diffusivity D.
+    #[arg(long, default_value_t = 1.0, value_parser = positive)]
+    pub diffusivity: f64,
+
+    /// The reaction term f, in the position x and the value y of u.
+    #[arg(long, default_value = "0", allow_hyphen_values = true)]
+    pub reaction: String,
+
+    /// The initial profile of u, in the position x.
+    #[arg(long, allow_hyphen_values = true)]
+    pub initial: String,
+
+    /// The condition at the left end: `dirichlet:VALUE` holds u there,
+    /// `neumann:VALUE` holds u_x, `periodic` joins the ends.
+    #[arg(long, default_value = "dirichlet:0", value_parser = boundary)]
+    pub left: End,
+
+    /// The condition at the right end, as for --left.
+    #[arg(long, default_value = "dirichlet:0", value_parser = boundary)]
+    pub right: End,
+
+    /// Chart to draw: a heatmap of u with time across and position up, or,
+    /// for a `.gif`, an animation of u along the grid.
+    #[arg(short, long, default_value = "pde.png")]
+    pub output: PathBuf,
+
+    /// Most frames of the animation.
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u32).range(1..))]
+    pub frames: u32,
+}
+
+impl PdeArgs {
+    /// The boundary conditions, or `None` unless both ends are periodic or
+    /// neither is.
+    pub fn boundaries(&self) -> Option<Boundaries> {
+        match (self.left, self.right) {
+            (End::Periodic, End::Periodic) => Some(Boundaries::Periodic),
+            (End::Condition(left), End::Condition(right)) => Some(Boundaries::Ends(left, right)),
+            _ => None,
+        }
+    }
+}
+
+/// The condition at one end of the domain of a [`PdeArgs`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum End {
+    Periodic,
+    Condition(Boundary),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+fn positive(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value),
+        Ok(_) => Err("must be a positive number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn nonzero(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if value != 0.0 && value.is_finite() => Ok(value),
+        Ok(_) => Err("must be a nonzero number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn non_negative(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if value >= 0.0 && value.is_finite() => Ok(value),
+        Ok(_) => Err("must not be negative".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn pair(s: &str) -> Result<(usize, usize), String> {
+    let (i, j) = s
+        .split_once(',')
+        .ok_or_else(|| "expected two component indices as `I,J`".to_string())?;
+
+    Ok((
+        i.trim().parse().map_err(|e| format!("`{i}`: {e}"))?,
+        j.trim().parse().map_err(|e| format!("`{j}`: {e}"))?,
+    ))
+}
+
+fn bounds(s: &str) -> Result<[f64; 2], String> {
+    let (lower, upper) = s
+        .split_once(',')
+        .ok_or_else(|| "expected two bounds as `LOWER,UPPER`".to_string())?;
+    let parse = |s: &str| s.trim().parse::<f64>().map_err(|e| format!("`{s}`: {e}"));
+
+    let (lower, upper) = (parse(lower)?, parse(upper)?);
+    if lower >= upper {
+        return Err("the lower bound must be less than the upper bound".to_string());
+    }
+    Ok([lower, upper])
+}
+
+fn grid(s: &str) -> Result<Axis, String> {
+    let [from, to, count] = s.split(',').collect::<Vec<_>>()[..] else {
+        return Err("expected `FROM,TO,COUNT`".to_string());
+    };
+    let parse = |s: &str| s.trim().parse::<f64>().map_err(|e| format!("`{s}`: {e}"));
+
+    Ok(Axis {
+        from: parse(from)?,
+        to: parse(to)?,
+        count: match count.trim().parse::<usize>() {
+            Ok(0) => return Err("the count must be at least 1".to_string()),
+            Ok(count) => count,
+            Err(e) => return Err(format!("`{count}`: {e}")),
+        },
+    })
+}
+
+fn steady_state(s: &str) -> Result<SteadyStateSettings, String> {
+    let (epsilon, steps) = match s.split_once(':') {
+        Some((epsilon, steps)) => (epsilon, Some(steps)),
+        None => (s, None),
+    };
+
+    let epsilon = positive(epsilon.trim())?;
+    let steps = match steps.map(str::trim) {
+        Some(steps) => match steps.parse::<usize>() {
+            Ok(0) => return Err("the number of steps must be positive".to_string()),
+            Ok(steps) => steps,
+            Err(e) => return Err(format!("`{steps}`: {e}")),
+        },
+        None => 10,
+    };
+    Ok(SteadyStateSettings { epsilon, steps })
+}
+
+fn row(s: &str) -> Result<Vec<String>, String> {
+    let entries: Vec<String> = s.split(';').map(|entry| entry.trim().to_string()).collect();
+    if entries.iter().any(String::is_empty) {
+        return Err("expected expressions separated by `;`".to_string());
+    }
+    Ok(entries)
+}
+
+fn condition(s: &str) -> Result<(usize, f64), String> {
+    let (i, value) = s
+        .split_once('=')
+        .ok_or_else(|| "expected a component and its value as `I=VALUE`".to_string())?;
+
+    Ok((
+        i.trim().parse().map_err(|e| format!("`{i}`: {e}"))?,
+        value
+            .trim()
+            .parse()
+            .map_err(|e| format!("`{value}`: {e}"))?,
+    ))
+}
+
+fn boundary(s: &str) -> Result<End, String> {
+    let (kind, value) = match s.split_once(':') {
+        Some((kind, value)) => (kind.trim(), Some(value.trim())),
+        None => (s.trim(), None),
+    };
+    let number = || -> Result<f64, String> {
+        let value = value.ok_or_else(|| format!("expected `{kind}:VALUE`"))?;
+        value.parse().map_err(|e| format!("`{value}`: {e}"))
+    };
+
+    match kind {
+        "dirichlet" => Ok(End::Condition(Boundary::Dirichlet(number()?))),
+        "neumann" => Ok(End::Condition(Boundary::Neumann(number()?))),
+        "periodic" if value.is_none() => Ok(End::Periodic),
+        _ => Err("expected `dirichlet:VALUE`, `neumann:VALUE` or `periodic`".to_string()),
+    }
+}
+
+fn event(s: &str) -> Result<EventSettings, String> {
+    let mut parts = s.split(':');
+    let expression = parts.next().unwrap_or_default().trim().to_string();
+
+    let direction = match parts.next().map(str::trim) {
+        Some(name) => Direction::from_name(name).ok_or_else(|| {
+            format!(
+                "unknown direction `{name}`, expected one of {}",
+                Direction::NAMES.join(", ")
+            )
+        })?,
+        None => Direction::default(),
+    };
+    let action = match parts.next().map(str::trim) {
+        Some(name) => Action::from_name(name).ok_or_else(|| {
+            format!(
+                "unknown action `{name}`, expected one of {}",
+                Action::NAMES.join(", ")
+            )
+        })?,
+        None => Action::default(),
+    };
+    if parts.next().is_some() {
+        return Err("expected `EXPR[:DIRECTION[:ACTION]]`".to_string());
+    }
+
+    Ok(EventSettings {
+        expression,
+        direction,
+        action,
+    })
+}
+
+impl Cli {
+    /// Parses the command line, exiting with a usage error for combinations
+    /// of arguments that cannot describe a sensible run.
+    pub fn parse_and_validate() -> Self {
+        let cli = Cli::parse();
+
+        if let Err(message) = cli.validate() {
+            Cli::command()
+                .error(ErrorKind::ArgumentConflict, message)
+                .exit();
+        }
+
+        cli
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let run = &self.run;
+
+        if let Some(Command::Export(export)) = &self.command {
+            if export.per_trajectory && export.output.as_os_str() == "-" {
+                return Err(
+                    "--per-trajectory needs an --output file to name the tables after".into(),
+                );
+            }
+            if export.per_trajectory && export.format() == ExportFormat::Json {
+                return Err("--per-trajectory only applies to CSV output".to_string());
+            }
+        }
+
+        if let Some(Command::Pde(pde)) = &self.command {
+            if pde.boundaries().is_none() {
+                return Err("--left and --right must both be periodic or neither".to_string());
+            }
+            if pde.domain.from >= pde.domain.to || pde.domain.count < 3 {
+                return Err("--domain needs FROM below TO and at least 3 points".to_string());
+            }
+        }
+
+        // scenario files are validated field by field when loaded
+        if self.scenario.is_some() {
+            return Ok(());
+        }
+
+        if let Some(max_x) = run.max_x.filter(|&max_x| max_x <= run.start_x) {
+            return Err(format!(
+                "--max-x ({max_x}) must be greater than --start-x ({})",
+                run.start_x
+            ));
+        }
+        if let Some(min_x) = run.min_x.filter(|&min_x| min_x >= run.start_x) {
+            return Err(format!(
+                "--min-x ({min_x}) must be less than --start-x ({})",
+                run.start_x
+            ));
+        }
+        let limits = [
+            run.max_x.is_some(),
+            run.min_x.is_some(),
+            run.max_abs_y.is_some(),
+            !run.y_box.is_empty(),
+            run.max_steps.is_some(),
+            run.time_limit.is_some(),
+            run.steady_state.is_some(),
+        ];
+        if run.all_limits && !limits.contains(&true) {
+            return Err("--all-limits needs at least one limit to combine".to_string());
+        }
+        if !run.y_box.is_empty() && run.y_box.len() != run.equations.len() {
+            return Err(format!(
+                "--y-box was given {} time(s) but {} equation(s) were given",
+                run.y_box.len(),
+                run.equations.len()
+            ));
+        }
+
+        if !run.jacobian.is_empty() {
+            let method = Method::from_name(&run.method, Tolerance::default());
+            if !method.is_some_and(Method::is_implicit) {
+                return Err(format!(
+                    "--jacobian only applies to implicit methods, not {}",
+                    run.method
+                ));
+            }
+            if run.jacobian.len() != run.equations.len() {
+                return Err(format!(
+                    "--jacobian was given {} time(s) but {} equation(s) were given",
+                    run.jacobian.len(),
+                    run.equations.len()
+                ));
+            }
+        }
+
+        let stochastic =
+            Method::from_name(&run.method, Tolerance::default()).is_some_and(Method::is_stochastic);
+        if stochastic && run.diffusion.is_empty() {
+            return Err(format!("{} needs --diffusion", run.method));
+        }
+        if !run.diffusion.is_empty() {
+            if !stochastic {
+                return Err(format!(
+                    "--diffusion only applies to stochastic methods, not {}",
+                    run.method
+                ));
+            }
+            if run.diffusion.len() != run.equations.len() {
+                return Err(format!(
+                    "--diffusion was given {} time(s) but {} equation(s) were given",
+                    run.diffusion.len(),
+                    run.equations.len()
+                ));
+            }
+        }
+
+        if !run.history.is_empty() && run.history.len() != run.equations.len() {
+            return Err(format!(
+                "--history was given {} time(s) but {} equation(s) were given",
+                run.history.len(),
+                run.equations.len()
+            ));
+        }
+
+        if run.start_y.len() != run.equations.len() {
+            return Err(format!(
+                "--start-y has {} value(s) but {} equation(s) were given",
+                run.start_y.len(),
+                run.equations.len()
+            ));
+        }
+
+        if run.atol == 0.0 && run.rtol == 0.0 {
+            return Err("--atol and --rtol cannot both be zero".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+impl RunArgs {
+    /// The scenario described by the command line options.
+    pub fn scenario(&self) -> Scenario {
+        // combined with all, a default limit could keep the others from ever
+        // ending a trajectory; the scenario supplies those on x
+        let default_limit = (!self.all_limits).then_some(150.0);
+
+        Scenario {
+            equations: self.equations.clone(),
+            method: self.method.clone(),
+            step: self.step_size,
+            bidirectional: self.bidirectional,
+            jacobian: (!self.jacobian.is_empty()).then(|| self.jacobian.clone()),
+            tolerance: Tolerance {
+                absolute: self.atol,
+                relative: self.rtol,
+            },
+            initial: InitialConditions::Spread {
+                start_x: self.start_x,
+                start_y: self.start_y.clone(),
+                y_spread: self.y_spread,
+                count: self.num_datasets as usize,
+            },
+            end: Limits {
+                max_x: self.max_x,
+                min_x: self.min_x,
+                max_abs_y: self.max_abs_y.or(default_limit),
+                y_box: (!self.y_box.is_empty()).then(|| self.y_box.clone()),
+                max_steps: self.max_steps.map(|steps| steps as usize),
+                time_limit: self.time_limit,
+                steady_state: self.steady_state,
+                combine: if self.all_limits {
+                    Combine::All
+                } else {
+                    Combine::Any
+                },
+            },
+            events: self.events.clone(),
+            resample: self.resample,
+            noise: (!self.diffusion.is_empty()).then(|| NoiseSettings {
+                diffusion: self.diffusion.clone(),
+                seed: self.seed,
+                paths: self.paths as usize,
+            }),
+            delay: (!self.delays.is_empty()).then(|| DelaySettings {
+                delays: self.delays.clone(),
+                history: (!self.history.is_empty()).then(|| self.history.clone()),
+            }),
+            plot: PlotSettings::default(),
+        }
+    }
+}
+
+impl PlotArgs {
+    pub fn apply(&self, settings: &mut PlotSettings) {
+        if let Some(output) = &self.output {
+            settings.output = output.clone();
+        }
+        if let Some(width) = self.width {
+            settings.style.width = width;
+        }
+        if let Some(height) = self.height {
+            settings.style.height = height;
+        }
+        if !self.pairs.is_empty() {
+            settings.pairs = self.pairs.clone();
+        }
+        if let Some(backend) = &self.backend {
+            settings.backend = Backend::from_name(backend);
+        }
+        if let Some(conserved) = &self.conserved {
+            settings.conserved = Some(conserved.clone());
+        }
+        if let Some([lower, upper]) = self.bands {
+            settings.ensemble = Some(EnsembleBands { lower, upper });
+        }
+        if self.direction_field {
+            let field = settings
+                .direction_field
+                .get_or_insert_with(Default::default);
+            field.colored |= self.color_slopes;
+        }
+        if self.phase_portrait {
+            let portrait = settings.phase_portrait.get_or_insert_with(Default::default);
+            portrait.colored |= self.color_speed;
+            portrait.nullclines &= !self.no_nullclines;
+            portrait.equilibria |= self.equilibria;
+            if settings.pairs.is_empty() {
+                settings.pairs = vec![(0, 1)];
+            }
+        }
+    }
+}
+
+impl ExportArgs {
+    pub fn format(&self) -> ExportFormat {
+        self.format.unwrap_or_else(|| {
+            if self.output.extension().is_some_and(|e| e == "json") {
+                ExportFormat::Json
+            } else {
+                ExportFormat::Csv
+            }
+        })
+    }
+}
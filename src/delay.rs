@@ -0,0 +1,216 @@
+//! Delay differential equations `y'(x) = f(x, y(x), y(x - tau_1), ...)`
+//! with constant delays.
+//!
+//! The past of a trajectory is the history function before its start and
+//! the cubic Hermite interpolant of the points taken so far after it, so
+//! the stepper sees an ordinary equation in `x` and `y`. A step longer than
+//! the shortest delay reaches past the last point, where the last step is
+//! extrapolated.
+
+use std::cell::RefCell;
+
+use crate::{
+    advance, event::Hermite, termination::Termination, Derivative, Event, Point, Stepper,
+    Trajectory,
+};
+
+/// Writes the state at `x`, before the start, into its last argument.
+pub type HistoryFunction<'a> = dyn Fn(f64, &mut [f64]) + 'a;
+
+/// Like a [`Derivative`], but of the current state followed by the state at
+/// `x` minus each delay in turn.
+pub type DelayedDerivative<'a> = dyn Fn(f64, &[f64], &mut [f64]) + 'a;
+
+/// The right-hand side of a delay differential equation, and the state
+/// before the start of its trajectories.
+pub struct DelaySystem<'a> {
+    /// The constant delays, all positive.
+    pub delays: Vec<f64>,
+    pub history: Box<HistoryFunction<'a>>,
+    pub derivative: Box<DelayedDerivative<'a>>,
+}
+
+/// The points of a trajectory so far, with the slope at each.
+struct Past {
+    points: Vec<Point>,
+    slopes: Vec<Vec<f64>>,
+}
+
+impl Past {
+    /// The state at `x`, at or after the start.
+    fn eval(&self, x: f64, out: &mut [f64]) {
+        let last = self.points.len() - 1;
+        if last == 0 {
+            let (point, slope) = (&self.points[0], &self.slopes[0]);
+            for (i, out) in out.iter_mut().enumerate() {
+                *out = point.y[i] + (x - point.x) * slope[i];
+            }
+            return;
+        }
+
+        let i = self.points.partition_point(|point| point.x <= x);
+        let i = i.clamp(1, last) - 1;
+        let point = Hermite {
+            start: &self.points[i],
+            end: &self.points[i + 1],
+            start_slope: &self.slopes[i],
+            end_slope: &self.slopes[i + 1],
+        }
+        .eval(x);
+        out.copy_from_slice(&point.y);
+    }
+}
+
+/// Records every point the wrapped stepper takes.
+struct Recording<'a, S: ?Sized> {
+    inner: &'a mut S,
+    past: &'a RefCell<Past>,
+}
+
+impl<S: Stepper + ?Sized> Stepper for Recording<'_, S> {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        self.inner.step(derivative, current, step_size);
+
+        let mut slope = vec![0.0; current.y.len()];
+        derivative(current.x, &current.y, &mut slope);
+        let mut past = self.past.borrow_mut();
+        past.points.push(current.clone());
+        past.slopes.push(slope);
+    }
+}
+
+/// Integrates a delay differential equation towards increasing x, as
+/// [`crate::integrate`] does an ordinary one.
+pub fn integrate<S: Stepper + ?Sized>(
+    start: Point,
+    step_size: f64,
+    stepper: &mut S,
+    termination: impl Termination,
+    events: &[Event],
+    system: &DelaySystem,
+) -> Trajectory {
+    let n = start.y.len();
+    let start_x = start.x;
+    let past = RefCell::new(Past {
+        points: vec![],
+        slopes: vec![],
+    });
+    // the current state followed by the delayed ones, reused between calls
+    let state = RefCell::new(vec![0.0; n * (system.delays.len() + 1)]);
+
+    let derivative = |x: f64, y: &[f64], dy: &mut [f64]| {
+        let mut state = state.borrow_mut();
+        state[..n].copy_from_slice(y);
+        for (j, delay) in system.delays.iter().enumerate() {
+            let at = x - delay;
+            let delayed = &mut state[(j + 1) * n..(j + 2) * n];
+            if at < start_x {
+                (system.history)(at, delayed);
+            } else {
+                past.borrow().eval(at, delayed);
+            }
+        }
+        (system.derivative)(x, &state, dy);
+    };
+
+    // the start itself is never delayed into, since the delays are positive
+    let mut slope = vec![0.0; n];
+    derivative(start.x, &start.y, &mut slope);
+    *past.borrow_mut() = Past {
+        points: vec![start.clone()],
+        slopes: vec![slope],
+    };
+
+    let mut recording = Recording {
+        inner: stepper,
+        past: &past,
+    };
+    advance(
+        start,
+        step_size,
+        &mut recording,
+        termination,
+        events,
+        derivative,
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::max_error, EndCondition, RungeKutta4};
+
+    #[test]
+    fn method_of_steps() {
+        // y'(x) = -y(x - 1) with y = 1 before 0 is solved piece by piece:
+        // 1 - x on [0, 1], then 1 - x + (x - 1)^2 / 2 on [1, 2]
+        let system = DelaySystem {
+            delays: vec![1.0],
+            history: Box::new(|_, y| y[0] = 1.0),
+            derivative: Box::new(|_, state, dy| dy[0] = -state[1]),
+        };
+        let trajectory = integrate(
+            (0.0, 1.0).into(),
+            0.05,
+            &mut RungeKutta4::default(),
+            EndCondition::until(2.0),
+            &[],
+            &system,
+        );
+
+        let exact = |x: f64| {
+            if x <= 1.0 {
+                1.0 - x
+            } else {
+                1.0 - x + (x - 1.0).powi(2) / 2.0
+            }
+        };
+        assert!(max_error(&trajectory.points, exact) < 1e-9);
+        assert!((trajectory.last().unwrap().x - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn several_delays_and_a_history() {
+        // y'(x) = y(x - 1) - y(x - 2) with y = x before 0 has
+        // y'(x) = (x - 1) - (x - 2) = 1 on [0, 1], so y = x carries on
+        let system = DelaySystem {
+            delays: vec![1.0, 2.0],
+            history: Box::new(|x, y| y[0] = x),
+            derivative: Box::new(|_, state, dy| dy[0] = state[1] - state[2]),
+        };
+        let trajectory = integrate(
+            (0.0, 0.0).into(),
+            0.1,
+            &mut RungeKutta4::default(),
+            EndCondition::until(1.0),
+            &[],
+            &system,
+        );
+        assert!(max_error(&trajectory.points, |x| x) < 1e-12);
+    }
+
+    #[test]
+    fn steps_longer_than_the_delay() {
+        let system = DelaySystem {
+            delays: vec![0.05],
+            history: Box::new(|x, y| y[0] = (-x).exp()),
+            derivative: Box::new(|_, state, dy| dy[0] = -state[1]),
+        };
+        let end = |step_size| {
+            let trajectory = integrate(
+                (0.0, 1.0).into(),
+                step_size,
+                &mut RungeKutta4::default(),
+                EndCondition::until(2.0),
+                &[],
+                &system,
+            );
+            trajectory.last().unwrap().y[0]
+        };
+
+        // extrapolating the last step costs accuracy, not stability
+        let (coarse, fine) = (end(0.2), end(0.01));
+        assert!((coarse - fine).abs() < 1e-3, "{coarse} vs {fine}");
+    }
+}
@@ -0,0 +1,335 @@
+//! Dense output: the solution at any x between the points of a trajectory.
+//!
+//! Each step is interpolated with the continuous extension of the method
+//! when it has one, like the fourth order interpolant of the Dormand–Prince
+//! pair, and otherwise with the cubic Hermite interpolant matching the value
+//! and the derivative at both ends of the step.
+
+use crate::{
+    advance, event::Hermite, stitch, termination::Termination, Event, Point, Stepper, Trajectory,
+};
+
+/// The continuous extension of one step from `x` to `x + step`:
+/// `y(x + theta step) = y(x) + step * sum_j coefficients[j] theta^(j + 1)`
+/// for each component of `y`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Continuous {
+    pub x: f64,
+    pub step: f64,
+    pub coefficients: Vec<[f64; 4]>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Hermite,
+    /// The extension of a step that began at the point with index `origin`.
+    Continuous {
+        origin: usize,
+        extension: Continuous,
+    },
+}
+
+/// A trajectory together with an interpolant of every step.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution {
+    pub trajectory: Trajectory,
+    slopes: Vec<Vec<f64>>,
+    // one per pair of consecutive points
+    pieces: Vec<Piece>,
+}
+
+impl Solution {
+    /// Interpolates every step of `trajectory` by cubic Hermite
+    /// interpolation, which suits any method.
+    pub fn hermite(trajectory: Trajectory, derivative: impl Fn(f64, &[f64], &mut [f64])) -> Self {
+        let steps = trajectory.points.len().saturating_sub(1);
+        Solution::new(trajectory, vec![None; steps], derivative)
+    }
+
+    /// Uses the extension of the step from each point to the next where it
+    /// spans exactly that step, which it does not for a step cut short by an
+    /// event or a termination condition.
+    fn new(
+        trajectory: Trajectory,
+        extensions: Vec<Option<Continuous>>,
+        derivative: impl Fn(f64, &[f64], &mut [f64]),
+    ) -> Self {
+        let points = &trajectory.points;
+        let slopes = points
+            .iter()
+            .map(|point| {
+                let mut slope = vec![0.0; point.y.len()];
+                derivative(point.x, &point.y, &mut slope);
+                slope
+            })
+            .collect();
+
+        let pieces = extensions
+            .into_iter()
+            .enumerate()
+            .map(|(i, extension)| match extension {
+                Some(extension) if extension.x + extension.step == points[i + 1].x => {
+                    Piece::Continuous {
+                        origin: i,
+                        extension,
+                    }
+                }
+                _ => Piece::Hermite,
+            })
+            .collect();
+
+        Solution {
+            trajectory,
+            slopes,
+            pieces,
+        }
+    }
+
+    pub fn points(&self) -> &[Point] {
+        &self.trajectory.points
+    }
+
+    /// The solution at `x`, or `None` outside the trajectory.
+    pub fn eval(&self, x: f64) -> Option<Point> {
+        let points = self.points();
+        let (first, last) = (points.first()?, points.last()?);
+        let increasing = last.x >= first.x;
+        if !(first.x.min(last.x)..=first.x.max(last.x)).contains(&x) {
+            return None;
+        }
+        if points.len() == 1 {
+            return Some(first.clone());
+        }
+
+        let beyond = points.partition_point(|point| {
+            if increasing {
+                point.x <= x
+            } else {
+                point.x >= x
+            }
+        });
+        let i = beyond.clamp(1, points.len() - 1) - 1;
+        if x == points[i].x {
+            return Some(points[i].clone());
+        }
+
+        Some(match &self.pieces[i] {
+            Piece::Hermite => Hermite {
+                start: &points[i],
+                end: &points[i + 1],
+                start_slope: &self.slopes[i],
+                end_slope: &self.slopes[i + 1],
+            }
+            .eval(x),
+            Piece::Continuous { origin, extension } => {
+                let theta = (x - extension.x) / extension.step;
+                let powers = [theta, theta.powi(2), theta.powi(3), theta.powi(4)];
+                let y = points[*origin]
+                    .y
+                    .iter()
+                    .zip(&extension.coefficients)
+                    .map(|(y, coefficients)| {
+                        let sum: f64 = coefficients.iter().zip(powers).map(|(c, p)| c * p).sum();
+                        y + extension.step * sum
+                    })
+                    .collect();
+                Point { x, y }
+            }
+        })
+    }
+
+    /// The solution at every x of `grid` that the trajectory covers.
+    pub fn resample(&self, grid: impl IntoIterator<Item = f64>) -> Vec<Point> {
+        grid.into_iter().filter_map(|x| self.eval(x)).collect()
+    }
+}
+
+/// Integrates as [`crate::integrate`] does, keeping the continuous
+/// extension of every step.
+pub fn integrate<S: Stepper + ?Sized>(
+    start: Point,
+    step_size: f64,
+    stepper: &mut S,
+    termination: impl Termination,
+    events: &[Event],
+    derivative: impl Fn(f64, &[f64], &mut [f64]),
+) -> Solution {
+    let mut extensions = vec![];
+    let trajectory = advance(
+        start,
+        step_size,
+        stepper,
+        termination,
+        events,
+        &derivative,
+        Some(&mut extensions),
+    );
+
+    Solution::new(trajectory, extensions, derivative)
+}
+
+/// Integrates both ways as [`crate::integrate_both`] does, keeping the
+/// continuous extension of every step.
+pub fn integrate_both<S: Stepper + ?Sized>(
+    start: Point,
+    step_size: f64,
+    backward: &mut S,
+    forward: &mut S,
+    mut termination: impl Termination,
+    events: &[Event],
+    derivative: impl Fn(f64, &[f64], &mut [f64]),
+) -> Solution {
+    let step_size = step_size.abs();
+    let before = integrate(
+        start.clone(),
+        -step_size,
+        backward,
+        &mut termination,
+        events,
+        &derivative,
+    );
+    let after = integrate(
+        start,
+        step_size,
+        forward,
+        &mut termination,
+        events,
+        &derivative,
+    );
+
+    // the backward branch is reversed, so the piece from each of its points
+    // to the next belongs to the step that began at the next
+    let count = before.trajectory.points.len();
+    let mut pieces: Vec<Piece> = before
+        .pieces
+        .into_iter()
+        .rev()
+        .map(|piece| match piece {
+            Piece::Continuous { origin, extension } => Piece::Continuous {
+                origin: count - 1 - origin,
+                extension,
+            },
+            Piece::Hermite => Piece::Hermite,
+        })
+        .collect();
+    let shared = usize::from(count > 0);
+    let offset = count - shared;
+    pieces.extend(after.pieces.into_iter().map(|piece| match piece {
+        Piece::Continuous { origin, extension } => Piece::Continuous {
+            origin: origin + offset,
+            extension,
+        },
+        Piece::Hermite => Piece::Hermite,
+    }));
+
+    let mut slopes = before.slopes;
+    slopes.reverse();
+    slopes.extend(after.slopes.into_iter().skip(shared));
+
+    Solution {
+        trajectory: stitch(before.trajectory, after.trajectory),
+        slopes,
+        pieces,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scalar, test_support::max_error, EndCondition, Method, RungeKutta4, Tolerance};
+
+    #[test]
+    fn hermite_between_fixed_steps() {
+        let trajectory = crate::integrate(
+            (0.0, 1.0).into(),
+            0.1,
+            &mut RungeKutta4::default(),
+            EndCondition::until(2.0),
+            &[],
+            scalar(|_, y| y),
+        );
+        let solution = Solution::hermite(trajectory, scalar(|_, y| y));
+
+        // the end at x = 2 is located to within rounding, maybe below it
+        let grid: Vec<f64> = (0..200).map(|i| i as f64 / 100.0).collect();
+        let resampled = solution.resample(grid.iter().copied());
+        assert_eq!(resampled.len(), grid.len());
+        assert!(max_error(&resampled, f64::exp) < 1e-4);
+
+        // knots are reproduced, and nothing is made up outside
+        let knot = &solution.points()[7];
+        assert_eq!(solution.eval(knot.x).as_ref(), Some(knot));
+        assert_eq!(solution.eval(-0.1), None);
+        assert_eq!(solution.resample([-1.0, 1.0, 3.0]).len(), 1);
+    }
+
+    #[test]
+    fn dormand_prince_interpolant() {
+        let tolerance = Tolerance {
+            absolute: 1e-10,
+            relative: 1e-10,
+        };
+        let mut stepper = Method::from_name("rk45", tolerance).unwrap().stepper();
+        let solution = integrate(
+            (0.0, [0.0, 1.0]).into(),
+            0.1,
+            stepper.as_mut(),
+            EndCondition::until(10.0),
+            &[],
+            |_, y, dy| {
+                dy[0] = y[1];
+                dy[1] = -y[0];
+            },
+        );
+
+        // every step but the last, cut short at x = 10, has its extension
+        let (last, rest) = solution.pieces.split_last().unwrap();
+        assert_eq!(*last, Piece::Hermite);
+        assert!(rest
+            .iter()
+            .all(|piece| matches!(piece, Piece::Continuous { .. })));
+
+        // the steps are long, so this tests the interpolant rather than the
+        // points
+        assert!(solution.points().len() < 300);
+        let resampled = solution.resample((0..=1000).map(|i| i as f64 / 100.0));
+        assert_eq!(resampled.len(), 1001);
+        assert!(max_error(&resampled, f64::sin) < 1e-8);
+    }
+
+    #[test]
+    fn both_ways() {
+        let mut backward = Method::from_name("rk45", Tolerance::default())
+            .unwrap()
+            .stepper();
+        let mut forward = Method::from_name("rk45", Tolerance::default())
+            .unwrap()
+            .stepper();
+        let solution = integrate_both(
+            (0.0, 1.0).into(),
+            0.1,
+            backward.as_mut(),
+            forward.as_mut(),
+            EndCondition {
+                min_x: Some(-2.0),
+                ..EndCondition::until(2.0)
+            },
+            &[],
+            scalar(|x, y| -2.0 * x * y),
+        );
+
+        let points = solution.points();
+        assert_eq!(solution.pieces.len() + 1, points.len());
+        assert_eq!(solution.slopes.len(), points.len());
+
+        let resampled = solution.resample((-200..=200).map(|i| i as f64 / 100.0));
+        assert_eq!(resampled.len(), 401);
+        let exact = |x: f64| (-x * x).exp();
+        assert!(max_error(&resampled, exact) < 2e-5);
+
+        // the extensions beat Hermite interpolation on steps this long
+        let hermite = Solution::hermite(solution.trajectory.clone(), scalar(|x, y| -2.0 * x * y));
+        let resampled = hermite.resample((-200..=200).map(|i| i as f64 / 100.0));
+        assert!(max_error(&resampled, exact) > 1e-4);
+    }
+}
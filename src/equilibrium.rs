@@ -0,0 +1,399 @@
+//! Fixed points of autonomous systems and the stability of their
+//! linearization.
+//!
+//! Equilibria are located by Newton iteration on `f(x, y) = 0` from a set of
+//! seeds, with the Jacobian estimated by central differences. Each one is
+//! then classified from the eigenvalues of its Jacobian; eigenvalues are
+//! computed for systems of one or two components.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::Derivative;
+
+const MAX_ITERATIONS: usize = 50;
+/// Newton stops once a step is this small relative to the size of `y`.
+const STEP_TOLERANCE: f64 = 1e-12;
+/// Largest norm of the derivative accepted at a converged point.
+const RESIDUAL_TOLERANCE: f64 = 1e-8;
+/// Roots closer than this, relative to their size, are the same equilibrium.
+const DISTINCT: f64 = 1e-6;
+
+/// The linear stability of an equilibrium.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Classification {
+    StableNode,
+    UnstableNode,
+    Saddle,
+    StableSpiral,
+    UnstableSpiral,
+    /// Purely imaginary eigenvalues. The linearization alone does not decide
+    /// the stability of a nonlinear system here.
+    Center,
+    /// A zero eigenvalue, or a mix of zero and imaginary ones.
+    NonHyperbolic,
+    /// The eigenvalues were not computed, for systems of more than two
+    /// components.
+    Unclassified,
+}
+
+impl Classification {
+    fn from_eigenvalues(eigenvalues: &[Eigenvalue], scale: f64) -> Self {
+        let zero = 1e-7 * scale.max(1.0);
+        let is_zero = |value: f64| value.abs() <= zero;
+
+        if eigenvalues.is_empty() {
+            Classification::Unclassified
+        } else if eigenvalues.iter().any(|e| is_zero(e.re)) {
+            if eigenvalues.iter().all(|e| is_zero(e.re) && !is_zero(e.im)) {
+                Classification::Center
+            } else {
+                Classification::NonHyperbolic
+            }
+        } else {
+            let oscillates = eigenvalues.iter().any(|e| !is_zero(e.im));
+            let stable = eigenvalues.iter().all(|e| e.re < 0.0);
+            let unstable = eigenvalues.iter().all(|e| e.re > 0.0);
+
+            match (stable, unstable, oscillates) {
+                (true, _, false) => Classification::StableNode,
+                (true, _, true) => Classification::StableSpiral,
+                (_, true, false) => Classification::UnstableNode,
+                (_, true, true) => Classification::UnstableSpiral,
+                _ => Classification::Saddle,
+            }
+        }
+    }
+
+    /// Whether nearby trajectories converge onto the equilibrium.
+    pub fn is_stable(self) -> bool {
+        matches!(
+            self,
+            Classification::StableNode | Classification::StableSpiral
+        )
+    }
+}
+
+impl fmt::Display for Classification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Classification::StableNode => "stable node",
+            Classification::UnstableNode => "unstable node",
+            Classification::Saddle => "saddle",
+            Classification::StableSpiral => "stable spiral",
+            Classification::UnstableSpiral => "unstable spiral",
+            Classification::Center => "center",
+            Classification::NonHyperbolic => "non-hyperbolic",
+            Classification::Unclassified => "unclassified",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Eigenvalue {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl fmt::Display for Eigenvalue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.im == 0.0 {
+            write!(f, "{}", self.re)
+        } else if self.im > 0.0 {
+            write!(f, "{} + {}i", self.re, self.im)
+        } else {
+            write!(f, "{} - {}i", self.re, -self.im)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Equilibrium {
+    pub y: Vec<f64>,
+    /// `jacobian[i][j]` is the derivative of component `i` of `f` with
+    /// respect to `y[j]`.
+    pub jacobian: Vec<Vec<f64>>,
+    pub eigenvalues: Vec<Eigenvalue>,
+    pub classification: Classification,
+}
+
+impl Equilibrium {
+    /// Linearizes `derivative` around `y` and classifies the result.
+    pub fn new(derivative: &Derivative, x: f64, y: Vec<f64>) -> Self {
+        let jacobian = jacobian(derivative, x, &y);
+        let eigenvalues = eigenvalues(&jacobian);
+        let scale = jacobian
+            .iter()
+            .flatten()
+            .fold(0.0, |max: f64, v| max.max(v.abs()));
+
+        Equilibrium {
+            classification: Classification::from_eigenvalues(&eigenvalues, scale),
+            y,
+            jacobian,
+            eigenvalues,
+        }
+    }
+}
+
+fn norm(v: &[f64]) -> f64 {
+    v.iter().map(|v| v * v).sum::<f64>().sqrt()
+}
+
+/// Estimates the Jacobian of `derivative` at `(x, y)` by central
+/// differences.
+pub fn jacobian(derivative: &Derivative, x: f64, y: &[f64]) -> Vec<Vec<f64>> {
+    let n = y.len();
+    let mut jacobian = vec![vec![0.0; n]; n];
+    let (mut forward, mut backward) = (vec![0.0; n], vec![0.0; n]);
+    let mut shifted = y.to_vec();
+
+    for j in 0..n {
+        let h = f64::EPSILON.cbrt() * y[j].abs().max(1.0);
+
+        shifted[j] = y[j] + h;
+        derivative(x, &shifted, &mut forward);
+        shifted[j] = y[j] - h;
+        derivative(x, &shifted, &mut backward);
+        shifted[j] = y[j];
+
+        for i in 0..n {
+            jacobian[i][j] = (forward[i] - backward[i]) / (2.0 * h);
+        }
+    }
+
+    jacobian
+}
+
+/// Solves `matrix · solution = rhs` by Gaussian elimination with partial
+/// pivoting, or `None` for a singular matrix.
+pub(crate) fn solve(mut matrix: Vec<Vec<f64>>, mut rhs: Vec<f64>) -> Option<Vec<f64>> {
+    let n = rhs.len();
+
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))?;
+        if matrix[pivot][column] == 0.0 || !matrix[pivot][column].is_finite() {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        rhs.swap(column, pivot);
+
+        let (above, below) = matrix.split_at_mut(column + 1);
+        let pivot_row = &above[column];
+        for (offset, row) in below.iter_mut().enumerate() {
+            let factor = row[column] / pivot_row[column];
+            for (value, pivot) in row[column..].iter_mut().zip(&pivot_row[column..]) {
+                *value -= factor * pivot;
+            }
+            rhs[column + 1 + offset] -= factor * rhs[column];
+        }
+    }
+
+    let mut solution = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (rhs[row] - sum) / matrix[row][row];
+    }
+
+    solution.iter().all(|v| v.is_finite()).then_some(solution)
+}
+
+/// Eigenvalues of a 1×1 or 2×2 matrix, or none for larger ones.
+fn eigenvalues(matrix: &[Vec<f64>]) -> Vec<Eigenvalue> {
+    match matrix {
+        [row] => vec![Eigenvalue {
+            re: row[0],
+            im: 0.0,
+        }],
+        [a, b] => {
+            let trace = a[0] + b[1];
+            let determinant = a[0] * b[1] - a[1] * b[0];
+            let discriminant = trace * trace / 4.0 - determinant;
+
+            if discriminant >= 0.0 {
+                let root = discriminant.sqrt();
+                vec![
+                    Eigenvalue {
+                        re: trace / 2.0 - root,
+                        im: 0.0,
+                    },
+                    Eigenvalue {
+                        re: trace / 2.0 + root,
+                        im: 0.0,
+                    },
+                ]
+            } else {
+                let root = (-discriminant).sqrt();
+                vec![
+                    Eigenvalue {
+                        re: trace / 2.0,
+                        im: -root,
+                    },
+                    Eigenvalue {
+                        re: trace / 2.0,
+                        im: root,
+                    },
+                ]
+            }
+        }
+        _ => vec![],
+    }
+}
+
+/// Runs Newton's method on `derivative(x, y) = 0` from `seed`, returning the
+/// root it converges to, if any.
+pub fn newton(derivative: &Derivative, x: f64, seed: Vec<f64>) -> Option<Vec<f64>> {
+    let mut y = seed;
+    let mut f = vec![0.0; y.len()];
+
+    for _ in 0..MAX_ITERATIONS {
+        derivative(x, &y, &mut f);
+        let step = solve(jacobian(derivative, x, &y), f.iter().map(|f| -f).collect())?;
+
+        for (y, step) in y.iter_mut().zip(&step) {
+            *y += step;
+        }
+        if y.iter().any(|y| !y.is_finite()) {
+            return None;
+        }
+
+        if norm(&step) <= STEP_TOLERANCE * (1.0 + norm(&y)) {
+            derivative(x, &y, &mut f);
+            return (norm(&f) <= RESIDUAL_TOLERANCE).then_some(y);
+        }
+    }
+
+    None
+}
+
+/// Finds the distinct equilibria Newton's method converges to from `seeds`,
+/// evaluating `derivative` at `x`, ordered by their coordinates.
+pub fn find(
+    derivative: &Derivative,
+    x: f64,
+    seeds: impl IntoIterator<Item = Vec<f64>>,
+) -> Vec<Equilibrium> {
+    let mut roots: Vec<Vec<f64>> = vec![];
+
+    for seed in seeds {
+        let Some(root) = newton(derivative, x, seed) else {
+            continue;
+        };
+
+        let distinct = |other: &Vec<f64>| {
+            let distance = norm(
+                &root
+                    .iter()
+                    .zip(other)
+                    .map(|(a, b)| a - b)
+                    .collect::<Vec<_>>(),
+            );
+            distance > DISTINCT * (1.0 + norm(&root))
+        };
+        if roots.iter().all(distinct) {
+            roots.push(root);
+        }
+    }
+
+    roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    roots
+        .into_iter()
+        .map(|y| Equilibrium::new(derivative, x, y))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::scenario::Axis;
+
+    fn seeds(from: f64, to: f64, count: usize) -> Vec<Vec<f64>> {
+        let axis = Axis { from, to, count };
+        Axis::grid(&[axis, axis])
+    }
+
+    #[test]
+    fn logistic() {
+        let logistic = |_: f64, y: &[f64], dy: &mut [f64]| dy[0] = y[0] * (1.0 - y[0]);
+        let seeds = (0..10).map(|i| vec![i as f64 * 0.3 - 1.0]);
+
+        let equilibria = find(&logistic, 0.0, seeds);
+        assert_eq!(equilibria.len(), 2);
+        assert!(equilibria[0].y[0].abs() < 1e-12);
+        assert!((equilibria[1].y[0] - 1.0).abs() < 1e-12);
+        assert_eq!(equilibria[0].classification, Classification::UnstableNode);
+        assert_eq!(equilibria[1].classification, Classification::StableNode);
+    }
+
+    #[test]
+    fn pendulum() {
+        let pendulum = |_: f64, y: &[f64], dy: &mut [f64]| {
+            dy[0] = y[1];
+            dy[1] = -y[0].sin();
+        };
+
+        let equilibria = find(&pendulum, 0.0, seeds(-4.0, 4.0, 9));
+        let found: Vec<_> = equilibria
+            .iter()
+            .map(|e| (e.y[0], e.classification))
+            .collect();
+        assert_eq!(found.len(), 3, "{found:?}");
+
+        for ((y0, classification), expected) in found.into_iter().zip([
+            (-PI, Classification::Saddle),
+            (0.0, Classification::Center),
+            (PI, Classification::Saddle),
+        ]) {
+            assert!((y0 - expected.0).abs() < 1e-10);
+            assert_eq!(classification, expected.1);
+        }
+    }
+
+    #[test]
+    fn spirals_and_nodes() {
+        let classify = |a: [f64; 4]| {
+            let linear = move |_: f64, y: &[f64], dy: &mut [f64]| {
+                dy[0] = a[0] * y[0] + a[1] * y[1];
+                dy[1] = a[2] * y[0] + a[3] * y[1];
+            };
+            let equilibria = find(&linear, 0.0, [vec![0.5, -0.5]]);
+            assert_eq!(equilibria.len(), 1);
+            assert!(norm(&equilibria[0].y) < 1e-12);
+            equilibria[0].classification
+        };
+
+        assert_eq!(
+            classify([0.0, 1.0, -1.0, -1.0]),
+            Classification::StableSpiral
+        );
+        assert_eq!(
+            classify([0.0, 1.0, -1.0, 1.0]),
+            Classification::UnstableSpiral
+        );
+        assert_eq!(classify([-1.0, 0.0, 0.0, -2.0]), Classification::StableNode);
+        assert_eq!(classify([1.0, 0.0, 0.0, 2.0]), Classification::UnstableNode);
+        assert_eq!(classify([1.0, 0.0, 0.0, -1.0]), Classification::Saddle);
+        assert_eq!(classify([0.0, 2.0, -0.5, 0.0]), Classification::Center);
+    }
+
+    #[test]
+    fn eigenvalues_of_the_linearization() {
+        let damped = |_: f64, y: &[f64], dy: &mut [f64]| {
+            dy[0] = y[1];
+            dy[1] = -y[0] - y[1];
+        };
+        let equilibrium = Equilibrium::new(&damped, 0.0, vec![0.0, 0.0]);
+
+        // -1/2 ± i √3/2
+        let [a, b] = equilibrium.eigenvalues[..] else {
+            panic!("{:?}", equilibrium.eigenvalues);
+        };
+        assert!((a.re + 0.5).abs() < 1e-8 && (b.re + 0.5).abs() < 1e-8);
+        assert!((b.im - 3f64.sqrt() / 2.0).abs() < 1e-8 && (a.im + b.im).abs() < 1e-12);
+    }
+}
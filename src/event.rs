@@ -0,0 +1,309 @@
+//! Events: zeros of a function `g(x, y)` along a trajectory.
+//!
+//! After every step the event functions are compared at both ends of the
+//! step. When one changes sign, the crossing is located by Brent's method on
+//! the cubic Hermite interpolant of the step, so its position does not
+//! depend on the step size beyond the accuracy of the interpolant.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Derivative, Point};
+
+/// Which sign changes of an event function count as a crossing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// From negative to zero or positive.
+    Rising,
+    /// From positive to zero or negative.
+    Falling,
+    #[default]
+    Both,
+}
+
+impl Direction {
+    pub const NAMES: &'static [&'static str] = &["rising", "falling", "both"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rising" => Some(Direction::Rising),
+            "falling" => Some(Direction::Falling),
+            "both" => Some(Direction::Both),
+            _ => None,
+        }
+    }
+
+    fn accepts(self, rising: bool) -> bool {
+        match self {
+            Direction::Rising => rising,
+            Direction::Falling => !rising,
+            Direction::Both => true,
+        }
+    }
+}
+
+/// What happens when an event function crosses zero.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Note the crossing and carry on integrating.
+    #[default]
+    Record,
+    /// End the trajectory at the crossing.
+    Terminate,
+}
+
+impl Action {
+    pub const NAMES: &'static [&'static str] = &["record", "terminate"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "record" => Some(Action::Record),
+            "terminate" => Some(Action::Terminate),
+            _ => None,
+        }
+    }
+}
+
+/// An event function `g(x, y)`.
+pub type EventFunction<'a> = dyn Fn(f64, &[f64]) -> f64 + Send + Sync + 'a;
+
+pub struct Event<'a> {
+    pub function: Box<EventFunction<'a>>,
+    pub direction: Direction,
+    pub action: Action,
+}
+
+impl<'a> Event<'a> {
+    pub fn new(
+        function: impl Fn(f64, &[f64]) -> f64 + Send + Sync + 'a,
+        direction: Direction,
+        action: Action,
+    ) -> Self {
+        Event {
+            function: Box::new(function),
+            direction,
+            action,
+        }
+    }
+}
+
+/// A located zero of an event function.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Crossing {
+    /// Index of the event in the list it was given in.
+    pub event: usize,
+    pub point: Point,
+    pub rising: bool,
+}
+
+/// The cubic Hermite interpolant of a step, matching the value and the
+/// derivative at both ends.
+pub(crate) struct Hermite<'a> {
+    pub start: &'a Point,
+    pub end: &'a Point,
+    pub start_slope: &'a [f64],
+    pub end_slope: &'a [f64],
+}
+
+impl Hermite<'_> {
+    pub fn eval(&self, x: f64) -> Point {
+        let h = self.end.x - self.start.x;
+        let t = (x - self.start.x) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        let (h00, h10, h01, h11) = (
+            2.0 * t3 - 3.0 * t2 + 1.0,
+            t3 - 2.0 * t2 + t,
+            3.0 * t2 - 2.0 * t3,
+            t3 - t2,
+        );
+
+        let y = (0..self.start.y.len())
+            .map(|i| {
+                h00 * self.start.y[i]
+                    + h10 * h * self.start_slope[i]
+                    + h01 * self.end.y[i]
+                    + h11 * h * self.end_slope[i]
+            })
+            .collect();
+
+        Point { x, y }
+    }
+}
+
+/// Locates a zero of `g` between `a` and `b`, where `fa` and `fb` have
+/// opposite signs, by Brent's method. Returns `x` with `g(x) = 0` exactly,
+/// or otherwise the end of the final bracket on the side of `a`.
+pub(crate) fn brent(mut g: impl FnMut(f64) -> f64, a: f64, b: f64, fa: f64, fb: f64) -> f64 {
+    let tolerance = f64::EPSILON * (a.abs() + b.abs());
+    let before = fa > 0.0;
+
+    let (mut a, mut b, mut fa, mut fb) = (a, b, fa, fb);
+    let (mut c, mut fc) = (b, fb);
+    let (mut d, mut e) = (b - a, b - a);
+
+    for _ in 0..100 {
+        if (fb > 0.0 && fc > 0.0) || (fb < 0.0 && fc < 0.0) {
+            (c, fc) = (a, fa);
+            d = b - a;
+            e = d;
+        }
+        if fc.abs() < fb.abs() {
+            (a, fa) = (b, fb);
+            (b, fb) = (c, fc);
+            (c, fc) = (a, fa);
+        }
+
+        let tol = 2.0 * f64::EPSILON * b.abs() + 0.5 * tolerance;
+        let middle = 0.5 * (c - b);
+        if fb == 0.0 {
+            return b;
+        }
+        if middle.abs() <= tol {
+            break;
+        }
+
+        if e.abs() >= tol && fa.abs() > fb.abs() {
+            // inverse quadratic interpolation, or secant when only two
+            // points are distinct
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * middle * s, 1.0 - s)
+            } else {
+                let (q, r) = (fa / fc, fb / fc);
+                (
+                    s * (2.0 * middle * q * (q - r) - (b - a) * (r - 1.0)),
+                    (q - 1.0) * (r - 1.0) * (s - 1.0),
+                )
+            };
+            if p > 0.0 {
+                q = -q;
+            }
+            p = p.abs();
+
+            if 2.0 * p < (3.0 * middle * q - (tol * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                d = middle;
+                e = d;
+            }
+        } else {
+            d = middle;
+            e = d;
+        }
+
+        (a, fa) = (b, fb);
+        b += if d.abs() > tol {
+            d
+        } else {
+            tol.copysign(middle)
+        };
+        fb = g(b);
+    }
+
+    if (fb > 0.0) == before {
+        b
+    } else {
+        c
+    }
+}
+
+/// Finds every crossing of `events` within the step from `start` to `end`,
+/// ordered along the step, which may go towards decreasing x.
+pub(crate) fn crossings(
+    events: &[&Event],
+    derivative: &Derivative,
+    start: &Point,
+    end: &Point,
+) -> Vec<Crossing> {
+    let mut slopes: Option<(Vec<f64>, Vec<f64>)> = None;
+    let mut crossings = vec![];
+
+    for (i, event) in events.iter().enumerate() {
+        let g0 = (event.function)(start.x, &start.y);
+        let g1 = (event.function)(end.x, &end.y);
+
+        // a zero at the start of the step was found at the end of the last
+        let rising = match (g0, g1) {
+            (g0, g1) if g0 < 0.0 && g1 >= 0.0 => true,
+            (g0, g1) if g0 > 0.0 && g1 <= 0.0 => false,
+            _ => continue,
+        };
+        // rising and falling refer to increasing x, whichever way the step
+        // went
+        let rising = rising == (end.x >= start.x);
+        if !event.direction.accepts(rising) {
+            continue;
+        }
+
+        let point = if g1 == 0.0 {
+            end.clone()
+        } else {
+            let (start_slope, end_slope) = slopes.get_or_insert_with(|| {
+                let mut start_slope = vec![0.0; start.y.len()];
+                let mut end_slope = vec![0.0; end.y.len()];
+                derivative(start.x, &start.y, &mut start_slope);
+                derivative(end.x, &end.y, &mut end_slope);
+                (start_slope, end_slope)
+            });
+            let interpolant = Hermite {
+                start,
+                end,
+                start_slope,
+                end_slope,
+            };
+
+            let g = |x: f64| {
+                let point = interpolant.eval(x);
+                (event.function)(x, &point.y)
+            };
+            interpolant.eval(brent(g, start.x, end.x, g0, g1))
+        };
+
+        crossings.push(Crossing {
+            event: i,
+            point,
+            rising,
+        });
+    }
+
+    crossings.sort_by(|a, b| {
+        let along = |crossing: &Crossing| (crossing.point.x - start.x).abs();
+        along(a).total_cmp(&along(b))
+    });
+    crossings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brent_converges() {
+        let root = brent(|x| x * x - 2.0, 0.0, 2.0, -2.0, 2.0);
+        assert!((root - 2f64.sqrt()).abs() < 1e-15);
+
+        // stays on the side of the first argument
+        let g = |x: f64| x.cos() - x;
+        let root = brent(g, 0.0, 1.0, g(0.0), g(1.0));
+        assert!(g(root) >= 0.0 && g(root) < 1e-15);
+    }
+
+    #[test]
+    fn hermite_is_exact_for_cubics() {
+        let y = |x: f64| x * x * x - x;
+        let dy = |x: f64| 3.0 * x * x - 1.0;
+        let (start, end) = ((0.5, y(0.5)).into(), (2.0, y(2.0)).into());
+        let interpolant = Hermite {
+            start: &start,
+            end: &end,
+            start_slope: &[dy(0.5)],
+            end_slope: &[dy(2.0)],
+        };
+
+        for x in [0.5, 0.7, 1.0, 1.9, 2.0] {
+            assert!((interpolant.eval(x).y[0] - y(x)).abs() < 1e-12);
+        }
+    }
+}
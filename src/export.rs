@@ -0,0 +1,291 @@
+//! Writing trajectories out for post-processing in other tools.
+//!
+//! Every format records the scenario the trajectories were computed from,
+//! so the equations, solver settings and initial conditions travel with the
+//! data. CSV files carry it as `#` comment lines ahead of the header.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::{
+    scenario::{Combine, Scenario},
+    shooting::BoundaryValueProblem,
+    Point,
+};
+
+fn write_metadata(output: &mut impl Write, scenario: &Scenario) -> io::Result<()> {
+    writeln!(output, "# equations: {}", scenario.equations.join("; "))?;
+    writeln!(output, "# method: {}", scenario.method)?;
+    if scenario.bidirectional {
+        writeln!(output, "# step: {}, both directions", scenario.step.abs())?;
+    } else {
+        writeln!(output, "# step: {}", scenario.step)?;
+    }
+    writeln!(
+        output,
+        "# tolerance: absolute {}, relative {}",
+        scenario.tolerance.absolute, scenario.tolerance.relative
+    )?;
+    if let Some(jacobian) = &scenario.jacobian {
+        let rows: Vec<String> = jacobian.iter().map(|row| row.join(", ")).collect();
+        writeln!(output, "# jacobian: {}", rows.join("; "))?;
+    }
+    let (end, x_range) = (&scenario.end, scenario.x_range());
+    write!(
+        output,
+        "# end: max_x {}, max_abs_y {}",
+        display_option(x_range.max),
+        display_option(end.max_abs_y)
+    )?;
+    if let Some(min_x) = x_range.min {
+        write!(output, ", min_x {min_x}")?;
+    }
+    if let Some(y_box) = &end.y_box {
+        write!(output, ", y_box {y_box:?}")?;
+    }
+    if let Some(max_steps) = end.max_steps {
+        write!(output, ", max_steps {max_steps}")?;
+    }
+    if let Some(time_limit) = end.time_limit {
+        write!(output, ", time_limit {time_limit}")?;
+    }
+    if let Some(steady_state) = end.steady_state {
+        write!(
+            output,
+            ", steady_state epsilon {} over {} steps",
+            steady_state.epsilon, steady_state.steps
+        )?;
+    }
+    if end.combine == Combine::All {
+        write!(output, ", all at once")?;
+    }
+    writeln!(output)?;
+
+    if let Some(noise) = &scenario.noise {
+        writeln!(
+            output,
+            "# noise: diffusion {}, seed {}, {} path(s) from each initial point",
+            noise.diffusion.join("; "),
+            noise.seed,
+            noise.paths
+        )?;
+    }
+    if let Some(delay) = &scenario.delay {
+        let delays: Vec<String> = delay.delays.iter().map(f64::to_string).collect();
+        write!(output, "# delays: {}", delays.join(", "))?;
+        match &delay.history {
+            Some(history) => writeln!(output, ", history {}", history.join("; "))?,
+            None => writeln!(output, ", history held at the initial value")?,
+        }
+    }
+    if let Some(grid) = scenario.resample {
+        writeln!(
+            output,
+            "# resampled: {} values of x from {} to {}",
+            grid.count, grid.from, grid.to
+        )?;
+    }
+    Ok(())
+}
+
+fn display_option(value: Option<f64>) -> String {
+    value.map_or("none".to_string(), |value| value.to_string())
+}
+
+fn write_initial(output: &mut impl Write, id: usize, initial: &Point) -> io::Result<()> {
+    writeln!(
+        output,
+        "# trajectory {id}: initial x {}, y {:?}",
+        initial.x, initial.y
+    )
+}
+
+fn write_header(output: &mut impl Write, id_column: bool, dimension: usize) -> io::Result<()> {
+    if id_column {
+        write!(output, "trajectory,")?;
+    }
+    write!(output, "x")?;
+    for i in 0..dimension {
+        write!(output, ",y{i}")?;
+    }
+    writeln!(output)
+}
+
+fn write_row(output: &mut impl Write, id: Option<usize>, point: &Point) -> io::Result<()> {
+    if let Some(id) = id {
+        write!(output, "{id},")?;
+    }
+    write!(output, "{}", point.x)?;
+    for y in &point.y {
+        write!(output, ",{y}")?;
+    }
+    writeln!(output)
+}
+
+/// Writes every trajectory into one long-format CSV table with columns
+/// `trajectory, x, y0, y1, ...`.
+pub fn write_long_csv(
+    output: &mut impl Write,
+    scenario: &Scenario,
+    datasets: &[Vec<Point>],
+) -> io::Result<()> {
+    write_metadata(output, scenario)?;
+    for (id, initial) in scenario.initial_points().iter().enumerate() {
+        write_initial(output, id, initial)?;
+    }
+
+    write_header(output, true, scenario.dimension())?;
+    for (id, points) in datasets.iter().enumerate() {
+        for point in points {
+            write_row(output, Some(id), point)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes trajectory `id` alone as a CSV table with columns `x, y0, y1, ...`.
+pub fn write_trajectory_csv(
+    output: &mut impl Write,
+    scenario: &Scenario,
+    id: usize,
+    points: &[Point],
+) -> io::Result<()> {
+    write_metadata(output, scenario)?;
+    if let Some(initial) = scenario.initial_points().get(id) {
+        write_initial(output, id, initial)?;
+    }
+
+    write_header(output, false, scenario.dimension())?;
+    for point in points {
+        write_row(output, None, point)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the solution of a boundary value problem as a CSV table with
+/// columns `x, y0, y1, ...`, recording its boundary conditions.
+pub fn write_solution_csv(
+    output: &mut impl Write,
+    scenario: &Scenario,
+    problem: &BoundaryValueProblem,
+    points: &[Point],
+) -> io::Result<()> {
+    write_metadata(output, scenario)?;
+    let [a, b] = problem.interval;
+    let conditions: Vec<String> = (problem.start.iter().map(|condition| (a, condition)))
+        .chain(problem.end.iter().map(|condition| (b, condition)))
+        .map(|(x, (i, value))| format!("y{i}({x}) = {value}"))
+        .collect();
+    writeln!(output, "# boundary: {}", conditions.join(", "))?;
+
+    write_header(output, false, scenario.dimension())?;
+    for point in points {
+        write_row(output, None, point)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    scenario: &'a Scenario,
+    trajectories: Vec<Trajectory<'a>>,
+}
+
+#[derive(Serialize)]
+struct Trajectory<'a> {
+    id: usize,
+    initial: Point,
+    points: &'a [Point],
+}
+
+/// Writes the scenario and every trajectory as one JSON document.
+pub fn write_json(
+    output: &mut impl Write,
+    scenario: &Scenario,
+    datasets: &[Vec<Point>],
+) -> io::Result<()> {
+    let document = Document {
+        scenario,
+        trajectories: scenario
+            .initial_points()
+            .into_iter()
+            .zip(datasets)
+            .enumerate()
+            .map(|(id, (initial, points))| Trajectory {
+                id,
+                initial,
+                points,
+            })
+            .collect(),
+    };
+
+    serde_json::to_writer_pretty(&mut *output, &document)?;
+    writeln!(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::InitialConditions;
+
+    fn scenario() -> Scenario {
+        let mut scenario: Scenario = toml::from_str(
+            r#"
+            equations = ["y1", "-y0"]
+            method = "rk4"
+            step = 0.5
+
+            [initial.list]
+            start_x = 0.0
+            y = [[1.0, 0.0], [2.0, 0.0]]
+            "#,
+        )
+        .unwrap();
+        scenario.end.max_x = Some(0.5);
+        scenario
+    }
+
+    fn datasets() -> Vec<Vec<Point>> {
+        vec![
+            vec![(0.0, [1.0, 0.0]).into(), (0.5, [0.875, -0.5]).into()],
+            vec![(0.0, [2.0, 0.0]).into()],
+        ]
+    }
+
+    #[test]
+    fn long_csv() {
+        let mut output = vec![];
+        write_long_csv(&mut output, &scenario(), &datasets()).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("# method: rk4\n"));
+        assert!(output.contains("# trajectory 1: initial x 0, y [2.0, 0.0]\n"));
+        assert!(output.ends_with("trajectory,x,y0,y1\n0,0,1,0\n0,0.5,0.875,-0.5\n1,0,2,0\n"));
+    }
+
+    #[test]
+    fn trajectory_csv() {
+        let mut output = vec![];
+        write_trajectory_csv(&mut output, &scenario(), 0, &datasets()[0]).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("# trajectory 0: initial x 0, y [1.0, 0.0]\n"));
+        assert!(output.ends_with("x,y0,y1\n0,1,0\n0.5,0.875,-0.5\n"));
+    }
+
+    #[test]
+    fn json_round_trips_scenario() {
+        let mut output = vec![];
+        write_json(&mut output, &scenario(), &datasets()).unwrap();
+        let document: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+        let scenario: Scenario = serde_json::from_value(document["scenario"].clone()).unwrap();
+        assert!(matches!(scenario.initial, InitialConditions::List { .. }));
+        assert_eq!(document["trajectories"][1]["initial"]["y"][0], 2.0);
+        assert_eq!(document["trajectories"][0]["points"][1]["y"][1], -0.5);
+    }
+}
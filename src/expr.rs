@@ -0,0 +1,628 @@
+//! Parsing and evaluation of right-hand sides written as text, such as
+//! `"cbrt(y) + x"` or `"sin(x)*y - y^3"`.
+//!
+//! Expressions may use the independent variable `x`, the state components
+//! `y0`, `y1`, ... (with `y` as a shorthand for `y0`), numeric literals, the
+//! constants `pi`, `e` and `tau`, the operators `+ - * / ^` and parentheses,
+//! and the functions listed in [`FUNCTIONS`]. The equations of a delay
+//! differential equation may also use `y{i}_{j}`, component `i` at `x`
+//! minus delay `j` (with `y_{j}` as a shorthand for `y0_{j}`).
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// 1-based column of the offending character.
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(column: usize, message: impl Into<String>) -> Self {
+        ParseError {
+            column,
+            message: message.into(),
+        }
+    }
+
+    /// Renders the error beneath `source` with a caret under the offending
+    /// column.
+    pub fn annotate(&self, source: &str) -> String {
+        format!("{}\n{}\n{:>width$}", self, source, "^", width = self.column)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Function {
+    Unary(fn(f64) -> f64),
+    Binary(fn(f64, f64) -> f64),
+}
+
+impl Function {
+    fn arity(self) -> usize {
+        match self {
+            Function::Unary(_) => 1,
+            Function::Binary(_) => 2,
+        }
+    }
+}
+
+/// Every function an expression may call, by name.
+pub const FUNCTIONS: &[(&str, Function)] = &[
+    ("sin", Function::Unary(f64::sin)),
+    ("cos", Function::Unary(f64::cos)),
+    ("tan", Function::Unary(f64::tan)),
+    ("asin", Function::Unary(f64::asin)),
+    ("acos", Function::Unary(f64::acos)),
+    ("atan", Function::Unary(f64::atan)),
+    ("sinh", Function::Unary(f64::sinh)),
+    ("cosh", Function::Unary(f64::cosh)),
+    ("tanh", Function::Unary(f64::tanh)),
+    ("exp", Function::Unary(f64::exp)),
+    ("ln", Function::Unary(f64::ln)),
+    ("log10", Function::Unary(f64::log10)),
+    ("log2", Function::Unary(f64::log2)),
+    ("sqrt", Function::Unary(f64::sqrt)),
+    ("cbrt", Function::Unary(f64::cbrt)),
+    ("abs", Function::Unary(f64::abs)),
+    ("sign", Function::Unary(f64::signum)),
+    ("floor", Function::Unary(f64::floor)),
+    ("ceil", Function::Unary(f64::ceil)),
+    ("round", Function::Unary(f64::round)),
+    ("min", Function::Binary(f64::min)),
+    ("max", Function::Binary(f64::max)),
+    ("pow", Function::Binary(f64::powf)),
+    ("atan2", Function::Binary(f64::atan2)),
+    ("hypot", Function::Binary(f64::hypot)),
+];
+
+const CONSTANTS: &[(&str, f64)] = &[
+    ("pi", std::f64::consts::PI),
+    ("e", std::f64::consts::E),
+    ("tau", std::f64::consts::TAU),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Number(f64),
+    X,
+    Y(usize),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+    Call(Function, Vec<Node>),
+}
+
+impl Node {
+    fn eval(&self, x: f64, y: &[f64]) -> f64 {
+        match self {
+            Node::Number(value) => *value,
+            Node::X => x,
+            Node::Y(i) => y[*i],
+            Node::Negate(node) => -node.eval(x, y),
+            Node::Binary(operator, left, right) => {
+                let (left, right) = (left.eval(x, y), right.eval(x, y));
+
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide => left / right,
+                    Operator::Power => left.powf(right),
+                }
+            }
+            Node::Call(Function::Unary(f), args) => f(args[0].eval(x, y)),
+            Node::Call(Function::Binary(f), args) => f(args[0].eval(x, y), args[1].eval(x, y)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Operator(char),
+    Open,
+    Close,
+    Comma,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "number `{value}`"),
+            Token::Ident(name) => write!(f, "`{name}`"),
+            Token::Operator(c) => write!(f, "`{c}`"),
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::End => write!(f, "end of input"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let token = if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // exponent, as in 1e-3
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+
+            let text: String = chars[start..i].iter().collect();
+            let value = text
+                .parse()
+                .map_err(|_| ParseError::new(column, format!("invalid number `{text}`")))?;
+            tokens.push((column, Token::Number(value)));
+            continue;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+
+            tokens.push((column, Token::Ident(chars[start..i].iter().collect())));
+            continue;
+        } else {
+            match c {
+                '+' | '-' | '*' | '/' | '^' => Token::Operator(c),
+                '(' => Token::Open,
+                ')' => Token::Close,
+                ',' => Token::Comma,
+                _ => {
+                    return Err(ParseError::new(
+                        column,
+                        format!("unexpected character `{c}`"),
+                    ))
+                }
+            }
+        };
+
+        tokens.push((column, token));
+        i += 1;
+    }
+
+    tokens.push((chars.len() + 1, Token::End));
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    dimension: usize,
+    delays: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].1
+    }
+
+    fn column(&self) -> usize {
+        self.tokens[self.position].0
+    }
+
+    fn next(&mut self) -> (usize, Token) {
+        let token = self.tokens[self.position].clone();
+        if self.position + 1 < self.tokens.len() {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        ParseError::new(
+            self.column(),
+            format!("expected {expected}, found {}", self.peek()),
+        )
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.term()?;
+
+        while let Token::Operator(c @ ('+' | '-')) = *self.peek() {
+            self.next();
+            let operator = if c == '+' {
+                Operator::Add
+            } else {
+                Operator::Subtract
+            };
+            node = Node::Binary(operator, Box::new(node), Box::new(self.term()?));
+        }
+
+        Ok(node)
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.unary()?;
+
+        while let Token::Operator(c @ ('*' | '/')) = *self.peek() {
+            self.next();
+            let operator = if c == '*' {
+                Operator::Multiply
+            } else {
+                Operator::Divide
+            };
+            node = Node::Binary(operator, Box::new(node), Box::new(self.unary()?));
+        }
+
+        Ok(node)
+    }
+
+    // unary := ('-' | '+') unary | power
+    fn unary(&mut self) -> Result<Node, ParseError> {
+        match self.peek() {
+            Token::Operator('-') => {
+                self.next();
+                Ok(Node::Negate(Box::new(self.unary()?)))
+            }
+            Token::Operator('+') => {
+                self.next();
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    // power := primary ('^' unary)?, so that -x^2 is -(x^2) and 2^3^2 is 2^9
+    fn power(&mut self) -> Result<Node, ParseError> {
+        let base = self.primary()?;
+
+        if *self.peek() == Token::Operator('^') {
+            self.next();
+            let exponent = self.unary()?;
+            return Ok(Node::Binary(
+                Operator::Power,
+                Box::new(base),
+                Box::new(exponent),
+            ));
+        }
+
+        Ok(base)
+    }
+
+    // primary := number | identifier | identifier '(' arguments ')' | '(' expression ')'
+    fn primary(&mut self) -> Result<Node, ParseError> {
+        match self.peek().clone() {
+            Token::Number(value) => {
+                self.next();
+                Ok(Node::Number(value))
+            }
+            Token::Open => {
+                self.next();
+                let node = self.expression()?;
+                self.close()?;
+                Ok(node)
+            }
+            Token::Ident(name) => {
+                let (column, _) = self.next();
+
+                if *self.peek() == Token::Open {
+                    self.call(&name, column)
+                } else {
+                    self.variable(&name, column)
+                }
+            }
+            _ => Err(self.unexpected("a number, variable or `(`")),
+        }
+    }
+
+    fn close(&mut self) -> Result<(), ParseError> {
+        if *self.peek() == Token::Close {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.unexpected("`)`"))
+        }
+    }
+
+    fn call(&mut self, name: &str, column: usize) -> Result<Node, ParseError> {
+        let function = FUNCTIONS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, function)| *function)
+            .ok_or_else(|| ParseError::new(column, format!("unknown function `{name}`")))?;
+
+        // skip the opening parenthesis
+        self.next();
+
+        let mut args = vec![];
+        if *self.peek() != Token::Close {
+            args.push(self.expression()?);
+            while *self.peek() == Token::Comma {
+                self.next();
+                args.push(self.expression()?);
+            }
+        }
+        self.close()?;
+
+        if args.len() != function.arity() {
+            return Err(ParseError::new(
+                column,
+                format!(
+                    "`{name}` takes {} argument(s) but {} were given",
+                    function.arity(),
+                    args.len()
+                ),
+            ));
+        }
+
+        Ok(Node::Call(function, args))
+    }
+
+    fn variable(&self, name: &str, column: usize) -> Result<Node, ParseError> {
+        if name == "x" {
+            return Ok(Node::X);
+        }
+
+        if let Some(&(_, value)) = CONSTANTS.iter().find(|(n, _)| *n == name) {
+            return Ok(Node::Number(value));
+        }
+
+        // y{i} or y{i}_{j}, with i left out for component 0
+        let parsed = name
+            .strip_prefix('y')
+            .and_then(|rest| match rest.split_once('_') {
+                Some((component, delay)) if !delay.is_empty() => {
+                    Some((index(component)?, Some(index(delay)?)))
+                }
+                Some(_) => None,
+                None => Some((index(rest)?, None)),
+            });
+        let Some((i, delay)) = parsed else {
+            return Err(ParseError::new(
+                column,
+                format!("unknown identifier `{name}`"),
+            ));
+        };
+
+        if i >= self.dimension {
+            return Err(ParseError::new(
+                column,
+                format!(
+                    "`{name}` refers to component {i}, but the system only has {} component(s)",
+                    self.dimension
+                ),
+            ));
+        }
+        match delay {
+            Some(j) if j >= self.delays => Err(ParseError::new(
+                column,
+                format!(
+                    "`{name}` refers to delay {j}, but {} delay(s) were given",
+                    self.delays
+                ),
+            )),
+            // the delayed states follow the current one, one per delay
+            Some(j) => Ok(Node::Y((j + 1) * self.dimension + i)),
+            None => Ok(Node::Y(i)),
+        }
+    }
+}
+
+/// Parses the index in `y{index}`, an empty string standing for 0.
+fn index(digits: &str) -> Option<usize> {
+    match digits {
+        "" => Some(0),
+        _ if digits.chars().all(|c| c.is_ascii_digit()) => digits.parse().ok(),
+        _ => None,
+    }
+}
+
+/// A parsed expression in `x` and the components of `y`.
+#[derive(Debug, Clone)]
+pub struct Expr {
+    root: Node,
+}
+
+impl Expr {
+    /// Parses an expression of a scalar equation, which may use `x` and `y`.
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        Self::parse_with_dimension(source, 1)
+    }
+
+    /// Parses an expression that may refer to the first `dimension`
+    /// components of `y`.
+    pub fn parse_with_dimension(source: &str, dimension: usize) -> Result<Self, ParseError> {
+        Self::parse_with_delays(source, dimension, 0)
+    }
+
+    /// Parses an expression that may also refer to the components of `y`
+    /// at each of `delays` delays. It is evaluated on the current state
+    /// followed by the delayed state for each delay in turn.
+    pub fn parse_with_delays(
+        source: &str,
+        dimension: usize,
+        delays: usize,
+    ) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+            dimension,
+            delays,
+        };
+
+        let root = parser.expression()?;
+        if *parser.peek() != Token::End {
+            return Err(parser.unexpected("an operator or end of input"));
+        }
+
+        Ok(Expr { root })
+    }
+
+    pub fn eval(&self, x: f64, y: &[f64]) -> f64 {
+        self.root.eval(x, y)
+    }
+}
+
+/// A system whose right-hand side is one [`Expr`] per component.
+#[derive(Debug, Clone)]
+pub struct ExprSystem {
+    equations: Vec<Expr>,
+}
+
+impl ExprSystem {
+    /// Parses one equation per component of the system. On failure, returns
+    /// the index of the offending equation alongside the error.
+    pub fn parse<S: AsRef<str>>(equations: &[S]) -> Result<Self, (usize, ParseError)> {
+        Self::parse_with_delays(equations, 0)
+    }
+
+    /// Parses the equations of a delay differential equation, see
+    /// [`Expr::parse_with_delays`].
+    pub fn parse_with_delays<S: AsRef<str>>(
+        equations: &[S],
+        delays: usize,
+    ) -> Result<Self, (usize, ParseError)> {
+        let equations = equations
+            .iter()
+            .enumerate()
+            .map(|(i, source)| {
+                Expr::parse_with_delays(source.as_ref(), equations.len(), delays)
+                    .map_err(|e| (i, e))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(ExprSystem { equations })
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.equations.len()
+    }
+
+    pub fn derivative(&self, x: f64, y: &[f64], dy: &mut [f64]) {
+        for (dy, equation) in dy.iter_mut().zip(&self.equations) {
+            *dy = equation.eval(x, y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str, x: f64, y: &[f64]) -> f64 {
+        Expr::parse_with_dimension(source, y.len())
+            .unwrap()
+            .eval(x, y)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3", 0.0, &[0.0]), 7.0);
+        assert_eq!(eval("(1 + 2) * 3", 0.0, &[0.0]), 9.0);
+        assert_eq!(eval("2 ^ 3 ^ 2", 0.0, &[0.0]), 512.0);
+        assert_eq!(eval("-x^2", 3.0, &[0.0]), -9.0);
+        assert_eq!(eval("8 / 4 / 2", 0.0, &[0.0]), 1.0);
+        assert_eq!(eval("1e-3 * 2.5E2", 0.0, &[0.0]), 0.25);
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        assert_eq!(eval("cbrt(y) + x", 1.0, &[8.0]), 3.0);
+        assert_eq!(eval("sin(x)*y - y^3", 0.0, &[2.0]), -8.0);
+        assert_eq!(eval("y0 * y1 + max(y1, 5)", 0.0, &[2.0, 3.0]), 11.0);
+        assert_eq!(eval("cos(pi) + ln(e)", 0.0, &[0.0]), 0.0);
+    }
+
+    #[test]
+    fn system() {
+        let system = ExprSystem::parse(&["y1", "-y0"]).unwrap();
+        let mut dy = [0.0; 2];
+        system.derivative(0.0, &[1.0, 2.0], &mut dy);
+
+        assert_eq!(dy, [2.0, -1.0]);
+    }
+
+    #[test]
+    fn delayed_components() {
+        // y0 = 1, y1 = 2 now, 3 and 4 at the first delay, 5 and 6 at the second
+        let state = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let eval = |source| {
+            Expr::parse_with_delays(source, 2, 2)
+                .unwrap()
+                .eval(0.0, &state)
+        };
+        assert_eq!(eval("y_0 + y1_0"), 7.0);
+        assert_eq!(eval("y0_1 * y1_1 - y"), 29.0);
+
+        let error = |source| Expr::parse_with_delays(source, 2, 2).unwrap_err().message;
+        assert_eq!(
+            error("y0_2"),
+            "`y0_2` refers to delay 2, but 2 delay(s) were given"
+        );
+        assert_eq!(error("y0_"), "unknown identifier `y0_`");
+        assert_eq!(error("y_x"), "unknown identifier `y_x`");
+        assert!(Expr::parse("y_0").is_err());
+    }
+
+    #[test]
+    fn error_columns() {
+        let error = |source| Expr::parse(source).unwrap_err();
+
+        assert_eq!(error("x + z").column, 5);
+        assert_eq!(error("x + z").message, "unknown identifier `z`");
+        assert_eq!(error("sinn(x)").column, 1);
+        assert_eq!(error("(x + 1").column, 7);
+        assert_eq!(error("x $ 2").column, 3);
+        assert_eq!(error("x + * 2").column, 5);
+        assert_eq!(error("max(x)").column, 1);
+        assert_eq!(error("x y").column, 3);
+        assert_eq!(error("y1").column, 1);
+    }
+
+    #[test]
+    fn annotation() {
+        let error = Expr::parse("x + z").unwrap_err();
+
+        assert_eq!(
+            error.annotate("x + z"),
+            "column 5: unknown identifier `z`\nx + z\n    ^"
+        );
+    }
+}
@@ -0,0 +1,423 @@
+//! Implicit methods for stiff systems.
+//!
+//! Each step solves an equation `y = rhs + gamma f(x, y)` for the new state
+//! by Newton's method, with the Jacobian of `f` either supplied or estimated
+//! by central differences. A step whose iteration does not converge leaves
+//! `y` as NaN, which ends the trajectory as degenerate.
+
+use std::{collections::VecDeque, sync::Arc};
+
+use crate::{
+    equilibrium::{self, solve},
+    Derivative, Point, Stepper, Tolerance,
+};
+
+const MAX_ITERATIONS: usize = 10;
+
+/// The Jacobian `df_i / dy_j` of a system at `(x, y)`, as rows.
+pub type JacobianFunction<'a> = dyn Fn(f64, &[f64]) -> Vec<Vec<f64>> + Send + Sync + 'a;
+
+#[derive(Clone)]
+struct Newton {
+    tolerance: Tolerance,
+    jacobian: Option<Arc<JacobianFunction<'static>>>,
+    f: Vec<f64>,
+}
+
+impl Newton {
+    fn new(tolerance: Tolerance, jacobian: Option<Arc<JacobianFunction<'static>>>) -> Self {
+        Newton {
+            tolerance,
+            jacobian,
+            f: vec![],
+        }
+    }
+
+    /// Solves `y = rhs + gamma f(x, y)`, starting from the guess in `y`.
+    fn solve(&mut self, derivative: &Derivative, x: f64, gamma: f64, rhs: &[f64], y: &mut [f64]) {
+        let n = y.len();
+        self.f.resize(n, 0.0);
+
+        for _ in 0..MAX_ITERATIONS {
+            derivative(x, y, &mut self.f);
+            let residual = (0..n).map(|i| rhs[i] + gamma * self.f[i] - y[i]).collect();
+
+            // the Jacobian of y - gamma f(x, y)
+            let mut matrix = match &self.jacobian {
+                Some(jacobian) => jacobian(x, y),
+                None => equilibrium::jacobian(derivative, x, y),
+            };
+            for (i, row) in matrix.iter_mut().enumerate() {
+                for entry in row.iter_mut() {
+                    *entry *= -gamma;
+                }
+                row[i] += 1.0;
+            }
+
+            let Some(step) = solve(matrix, residual) else {
+                break;
+            };
+            let Tolerance { absolute, relative } = self.tolerance;
+            let error = (0..n)
+                .map(|i| {
+                    y[i] += step[i];
+                    (step[i] / (absolute + relative * y[i].abs())).powi(2)
+                })
+                .sum::<f64>();
+
+            if (error / n.max(1) as f64).sqrt() <= 1.0 {
+                return;
+            }
+        }
+
+        y.fill(f64::NAN);
+    }
+}
+
+/// Backward Euler, first order and L-stable.
+#[derive(Clone)]
+pub struct BackwardEuler {
+    newton: Newton,
+    k: Vec<f64>,
+}
+
+impl BackwardEuler {
+    /// `tolerance` bounds the error of the Newton iterations, which use
+    /// `jacobian` when given and estimate the Jacobian otherwise.
+    pub fn new(tolerance: Tolerance, jacobian: Option<Arc<JacobianFunction<'static>>>) -> Self {
+        BackwardEuler {
+            newton: Newton::new(tolerance, jacobian),
+            k: vec![],
+        }
+    }
+}
+
+impl Stepper for BackwardEuler {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let (x, h) = (current.x, step_size);
+        self.k.resize(current.y.len(), 0.0);
+        derivative(x, &current.y, &mut self.k);
+
+        // forward Euler predicts
+        let rhs = current.y.clone();
+        for (y, k) in current.y.iter_mut().zip(&self.k) {
+            *y += h * k;
+        }
+
+        self.newton
+            .solve(derivative, x + h, h, &rhs, &mut current.y);
+        current.x = x + h;
+    }
+}
+
+/// The Crank–Nicolson method (implicit trapezoidal rule), second order and
+/// A-stable.
+#[derive(Clone)]
+pub struct CrankNicolson {
+    newton: Newton,
+    k: Vec<f64>,
+}
+
+impl CrankNicolson {
+    /// See [`BackwardEuler::new`].
+    pub fn new(tolerance: Tolerance, jacobian: Option<Arc<JacobianFunction<'static>>>) -> Self {
+        CrankNicolson {
+            newton: Newton::new(tolerance, jacobian),
+            k: vec![],
+        }
+    }
+}
+
+impl Stepper for CrankNicolson {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let (x, h) = (current.x, step_size);
+        self.k.resize(current.y.len(), 0.0);
+        derivative(x, &current.y, &mut self.k);
+
+        let rhs: Vec<f64> = current
+            .y
+            .iter()
+            .zip(&self.k)
+            .map(|(y, k)| y + h / 2.0 * k)
+            .collect();
+        for (y, k) in current.y.iter_mut().zip(&self.k) {
+            *y += h * k;
+        }
+
+        self.newton
+            .solve(derivative, x + h, h / 2.0, &rhs, &mut current.y);
+        current.x = x + h;
+    }
+}
+
+// y_{n+1} = sum_j ALPHA[k][j] y_{n-j} + BETA[k] h f(x_{n+1}, y_{n+1}) for
+// the formula of order k + 1
+const ALPHA: [&[f64]; 5] = [
+    &[1.0],
+    &[4.0 / 3.0, -1.0 / 3.0],
+    &[18.0 / 11.0, -9.0 / 11.0, 2.0 / 11.0],
+    &[48.0 / 25.0, -36.0 / 25.0, 16.0 / 25.0, -3.0 / 25.0],
+    &[
+        300.0 / 137.0,
+        -300.0 / 137.0,
+        200.0 / 137.0,
+        -75.0 / 137.0,
+        12.0 / 137.0,
+    ],
+];
+const BETA: [f64; 5] = [1.0, 2.0 / 3.0, 6.0 / 11.0, 12.0 / 25.0, 60.0 / 137.0];
+
+/// Backward differentiation formulas of variable order, from 1 to
+/// `max_order`, at most 5.
+///
+/// After each step the local errors of the formulas one order below, at
+/// and one order above the current one are estimated from backward
+/// differences of the last states. The next steps take the order whose
+/// error, weighed against the tolerance, would allow the largest step, but
+/// an order is kept for at least `order + 1` steps before it changes. The
+/// step size itself stays the one passed in. A trajectory starts at first
+/// order, and a step size different from the last, or a state other than
+/// the last one produced, starts over.
+#[derive(Clone)]
+pub struct Bdf {
+    pub max_order: usize,
+    newton: Newton,
+    // most recent first, enough for the error at the highest order
+    history: VecDeque<Vec<f64>>,
+    // x and step size of the last step
+    last: Option<(f64, f64)>,
+    order: usize,
+    // taken at the current order
+    steps: usize,
+    k: Vec<f64>,
+}
+
+impl Bdf {
+    /// See [`BackwardEuler::new`]; `tolerance` also weighs the error
+    /// estimates that choose the order. `max_order` is clamped to 1 to 5.
+    pub fn new(
+        max_order: usize,
+        tolerance: Tolerance,
+        jacobian: Option<Arc<JacobianFunction<'static>>>,
+    ) -> Self {
+        Bdf {
+            max_order: max_order.clamp(1, ALPHA.len()),
+            newton: Newton::new(tolerance, jacobian),
+            history: VecDeque::new(),
+            last: None,
+            order: 1,
+            steps: 0,
+            k: vec![],
+        }
+    }
+
+    /// The order of the next step.
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// The local error of the formula of `order` over the last step, scaled
+    /// by the tolerance, from the backward difference `h^(order + 1)
+    /// y^(order + 1)` of the last `order + 2` states. `None` until there
+    /// are that many.
+    fn error(&self, order: usize) -> Option<f64> {
+        let states = order + 2;
+        if self.history.len() < states {
+            return None;
+        }
+
+        let Tolerance { absolute, relative } = self.newton.tolerance;
+        let newest = &self.history[0];
+        let constant = BETA[order - 1] / (order + 1) as f64;
+        let sum = (0..newest.len())
+            .map(|i| {
+                let mut binomial = 1.0;
+                let mut difference = 0.0;
+                for (j, y) in self.history.iter().take(states).enumerate() {
+                    difference += binomial * y[i];
+                    binomial *= -((states - 1 - j) as f64) / (j + 1) as f64;
+                }
+                (constant * difference / (absolute + relative * newest[i].abs())).powi(2)
+            })
+            .sum::<f64>();
+
+        Some((sum / newest.len().max(1) as f64).sqrt())
+    }
+
+    /// Moves to the neighbouring order if its error allows a larger step.
+    fn select_order(&mut self) {
+        if self.steps <= self.order {
+            return;
+        }
+        // the step each order could take relative to this one, for the same
+        // scaled error
+        let factor = |order: usize| {
+            self.error(order)
+                .map(|error| error.powf(-1.0 / (order + 1) as f64))
+        };
+
+        let Some(mut best) = factor(self.order) else {
+            return;
+        };
+        let mut order = self.order;
+        let neighbours = [self.order - 1, self.order + 1];
+        for candidate in neighbours
+            .into_iter()
+            .filter(|&q| (1..=self.max_order).contains(&q))
+        {
+            if let Some(factor) = factor(candidate).filter(|&factor| factor > best) {
+                (best, order) = (factor, candidate);
+            }
+        }
+
+        if order != self.order {
+            self.order = order;
+            self.steps = 0;
+        }
+    }
+}
+
+impl Stepper for Bdf {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let (x, h) = (current.x, step_size);
+        if self.last != Some((x, h)) || self.history.front() != Some(&current.y) {
+            self.history.clear();
+            self.history.push_front(current.y.clone());
+            self.order = 1;
+            self.steps = 0;
+        }
+
+        let order = self.order;
+        let mut rhs = vec![0.0; current.y.len()];
+        for (alpha, y) in ALPHA[order - 1].iter().zip(&self.history) {
+            for (rhs, y) in rhs.iter_mut().zip(y) {
+                *rhs += alpha * y;
+            }
+        }
+
+        self.k.resize(current.y.len(), 0.0);
+        derivative(x, &current.y, &mut self.k);
+        for (y, k) in current.y.iter_mut().zip(&self.k) {
+            *y += h * k;
+        }
+
+        self.newton
+            .solve(derivative, x + h, BETA[order - 1] * h, &rhs, &mut current.y);
+        current.x = x + h;
+
+        self.history.push_front(current.y.clone());
+        self.history.truncate(self.max_order + 2);
+        self.last = Some((current.x, h));
+        self.steps += 1;
+        self.select_order();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        integrate, scalar, test_support::convergence_ratio, EndCondition, Euler, Method, Stop,
+        Trajectory,
+    };
+
+    /// y' = -1000 (y - cos x), stiff with a time constant of 1/1000.
+    fn stiff(x: f64, y: f64) -> f64 {
+        -1000.0 * (y - x.cos())
+    }
+
+    fn stiff_exact(x: f64) -> f64 {
+        // particular solution plus the transient from y(0) = 0
+        let particular = |x: f64| (1e6 * x.cos() + 1e3 * x.sin()) / (1e6 + 1.0);
+        particular(x) - particular(0.0) * (-1000.0 * x).exp()
+    }
+
+    fn run(stepper: &mut dyn Stepper, step_size: f64, max_x: f64) -> Trajectory {
+        let end_condition = EndCondition::until(max_x);
+        integrate(
+            (0.0, 0.0).into(),
+            step_size,
+            stepper,
+            end_condition,
+            &[],
+            scalar(stiff),
+        )
+    }
+
+    // Crank–Nicolson is not L-stable and damps the transient only by a factor
+    // of (1 - 500 h) / (1 + 500 h) per step, so wait it out
+    fn error_after_transient(trajectory: &Trajectory) -> f64 {
+        trajectory
+            .points
+            .iter()
+            .filter(|p| p.x > 1.0)
+            .map(|p| (p.y[0] - stiff_exact(p.x)).abs())
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn stiff_with_large_steps() {
+        // explicit Euler needs h < 0.002 to stay stable
+        let explicit = run(&mut Euler::default(), 0.01, 10.0);
+        assert!(matches!(explicit.stop, Stop::Degenerate { .. }));
+
+        for name in ["backward-euler", "crank-nicolson", "bdf2", "bdf5"] {
+            let method = Method::from_name(name, Tolerance::default()).unwrap();
+            let trajectory = run(method.stepper().as_mut(), 0.01, 10.0);
+
+            assert!(matches!(trajectory.stop, Stop::Condition(_)), "{name}");
+            let error = error_after_transient(&trajectory);
+            assert!(error < 1e-4, "{name}: {error}");
+        }
+    }
+
+    #[test]
+    fn supplied_jacobian() {
+        let jacobian: Arc<JacobianFunction> = Arc::new(|_, _| vec![vec![-1000.0]]);
+        let mut stepper = Bdf::new(3, Tolerance::default(), Some(jacobian));
+        let trajectory = run(&mut stepper, 0.05, 5.0);
+        let error = error_after_transient(&trajectory);
+        assert!(error < 1e-4, "{error}");
+    }
+
+    #[test]
+    fn order_of_convergence() {
+        let tolerance = Tolerance {
+            absolute: 1e-12,
+            relative: 1e-12,
+        };
+        let ratio = |name| {
+            let method = Method::from_name(name, tolerance).unwrap();
+            convergence_ratio(|| method.stepper(), 0.02)
+        };
+
+        assert!((ratio("backward-euler") - 2.0).abs() < 0.2);
+        assert!((ratio("crank-nicolson") - 4.0).abs() < 0.4);
+        assert!((ratio("bdf2") - 4.0).abs() < 0.4);
+    }
+
+    #[test]
+    fn order_follows_the_error() {
+        // y' = -y + H(x - 1) has a kink at x = 1 that higher orders cannot see past
+        let f = scalar(|x, y| -y + if x < 1.0 { 0.0 } else { 1.0 });
+        let orders = |max_order| {
+            let mut stepper = Bdf::new(max_order, Tolerance::default(), None);
+            let mut point: Point = (0.0, 1.0).into();
+            (0..200)
+                .map(|_| {
+                    stepper.step(&f, &mut point, 0.01);
+                    stepper.order()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let orders_up_to_5 = orders(5);
+        let (before, after) = orders_up_to_5.split_at(100);
+        assert_eq!(before[..3], [1, 1, 2]);
+        assert_eq!(before.last(), Some(&5));
+        assert!(after[..30].iter().any(|&order| order <= 3), "{after:?}");
+        assert_eq!(after.last(), Some(&5));
+
+        assert_eq!(orders(2).into_iter().max(), Some(2));
+    }
+}
@@ -0,0 +1,734 @@
+mod adaptive;
+pub mod delay;
+pub mod dense;
+pub mod equilibrium;
+pub mod event;
+pub mod export;
+pub mod expr;
+mod implicit;
+mod multistep;
+pub mod pde;
+pub mod phase;
+pub mod plot;
+pub mod scenario;
+pub mod shooting;
+mod stepper;
+pub mod stochastic;
+pub mod symplectic;
+pub mod termination;
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use dense::Continuous;
+use event::{Action, Crossing, Direction, Event};
+use termination::{Progress, Reached, Termination, XRange};
+
+pub use adaptive::{DormandPrince, StepStats, Tolerance};
+pub use implicit::{BackwardEuler, Bdf, CrankNicolson, JacobianFunction};
+pub use multistep::AdamsBashforthMoulton;
+pub use stepper::{Euler, Heun, Method, Midpoint, RungeKutta4, Stepper};
+pub use symplectic::{StormerVerlet, SymplecticEuler, Yoshida4};
+
+/// A state of the system: the independent variable `x` and the value of
+/// every component of `y` at that point.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: Vec<f64>,
+}
+
+impl From<(f64, f64)> for Point {
+    fn from((x, y): (f64, f64)) -> Self {
+        Point { x, y: vec![y] }
+    }
+}
+
+impl From<(f64, Vec<f64>)> for Point {
+    fn from((x, y): (f64, Vec<f64>)) -> Self {
+        Point { x, y }
+    }
+}
+
+impl<const N: usize> From<(f64, [f64; N])> for Point {
+    fn from((x, y): (f64, [f64; N])) -> Self {
+        Point { x, y: y.to_vec() }
+    }
+}
+
+impl Point {
+    pub fn is_degenerate(&self) -> bool {
+        is_degenerate(self.x) || self.y.iter().copied().any(is_degenerate)
+    }
+}
+
+/// The right-hand side of a system `y' = f(x, y)`, writing the derivative of
+/// each component of `y` into its last argument.
+pub type Derivative<'a> = dyn Fn(f64, &[f64], &mut [f64]) + 'a;
+
+pub fn is_degenerate(x: f64) -> bool {
+    matches!(
+        x.classify(),
+        std::num::FpCategory::Nan | std::num::FpCategory::Infinite
+    )
+}
+
+/// Adapts a scalar equation `y' = f(x, y)` to the system form expected by
+/// [`create_dataset`].
+pub fn scalar(derivative_y: impl Fn(f64, f64) -> f64) -> impl Fn(f64, &[f64], &mut [f64]) {
+    move |x, y, dy| dy[0] = derivative_y(x, y[0])
+}
+
+/// Reduces an n-th order equation `y^(n) = f(x, y, y', ..., y^(n-1))` to a
+/// first-order system whose state is `[y, y', ..., y^(n-1)]`.
+///
+/// The order is the length of the state, so the starting point must carry
+/// all `n` initial values. Component `0` of the solution is `y` itself and
+/// component `k` its k-th derivative.
+pub fn reduce_order(
+    nth_derivative: impl Fn(f64, &[f64]) -> f64,
+) -> impl Fn(f64, &[f64], &mut [f64]) {
+    move |x, y, dy| {
+        let n = y.len();
+
+        dy[..n - 1].copy_from_slice(&y[1..]);
+        dy[n - 1] = nth_derivative(x, y);
+    }
+}
+
+pub struct EndCondition {
+    pub max_x: Option<f64>,
+    /// Bounds integration towards decreasing x.
+    pub min_x: Option<f64>,
+    /// Applies to every component of `y`.
+    pub max_abs_y: Option<f64>,
+}
+
+impl EndCondition {
+    /// Ends at `max_x`, with no other bound.
+    pub fn until(max_x: f64) -> Self {
+        EndCondition {
+            max_x: Some(max_x),
+            min_x: None,
+            max_abs_y: None,
+        }
+    }
+}
+
+impl Termination for EndCondition {
+    fn check(&mut self, progress: &Progress) -> Option<Reached> {
+        let mut x_range = XRange {
+            min: self.min_x,
+            max: self.max_x,
+        };
+        if let Some(reached) = x_range.check(progress) {
+            return Some(reached);
+        }
+
+        let max_y = self.max_abs_y?;
+        let largest = |y: &[f64]| y.iter().fold(0.0, |max: f64, y| max.max(y.abs()));
+        (largest(&progress.point.y) >= max_y).then(|| {
+            Reached::on(format!("|y| reached {max_y}"), move |_, y| {
+                largest(y) - max_y
+            })
+        })
+    }
+}
+
+/// Why a trajectory ended.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stop {
+    /// A termination condition holds, as described.
+    Condition(String),
+    /// The event with this index crossed zero, and terminates.
+    Event(usize),
+    /// The state at `x` would have left the finite numbers, so the
+    /// trajectory ends at the last finite state before it.
+    Degenerate { x: f64 },
+}
+
+impl std::fmt::Display for Stop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stop::Condition(reason) => f.write_str(reason),
+            Stop::Event(i) => write!(f, "event {i}"),
+            Stop::Degenerate { x } => write!(f, "blow-up at x = {x}"),
+        }
+    }
+}
+
+/// The points of an integrated trajectory, the crossings of its events, what
+/// ended it and how long it took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trajectory {
+    pub points: Vec<Point>,
+    pub crossings: Vec<Crossing>,
+    pub stop: Stop,
+    /// What ended the backward branch, at the first point, of a trajectory
+    /// integrated in both directions.
+    pub backward_stop: Option<Stop>,
+    /// Steps taken, counting the last one even when it was cut short.
+    pub steps: usize,
+    /// Wall-clock time spent integrating.
+    pub elapsed: Duration,
+}
+
+impl Trajectory {
+    /// The last finite state, or `None` when the trajectory ended at its
+    /// start.
+    pub fn last(&self) -> Option<&Point> {
+        self.points.last()
+    }
+
+    /// What ended each branch, the backward one first.
+    pub fn stops(&self) -> impl Iterator<Item = &Stop> {
+        self.backward_stop.iter().chain([&self.stop])
+    }
+}
+
+/// Integrates the system `y' = derivative(x, y)` from `start`, where
+/// `derivative` writes the derivative of each component of `y` into its
+/// last argument.
+///
+/// A negative `step_size` integrates towards decreasing x. The trajectory
+/// ends when `termination` holds, on its boundary when it
+/// has one, at the first terminating crossing of `events`, or before the
+/// first degenerate point. The crossings of every event up to then are
+/// recorded in the order they happened, indexed by their position in
+/// `events`.
+pub fn integrate<S: Stepper + ?Sized>(
+    start: Point,
+    step_size: f64,
+    stepper: &mut S,
+    termination: impl Termination,
+    events: &[Event],
+    derivative: impl Fn(f64, &[f64], &mut [f64]),
+) -> Trajectory {
+    advance(
+        start,
+        step_size,
+        stepper,
+        termination,
+        events,
+        derivative,
+        None,
+    )
+}
+
+/// Integrates the system `y' = derivative(x, y)` from `start` both ways,
+/// first backwards with `backward` and then forwards with `forward`, and
+/// stitches the branches into one trajectory ordered by increasing x.
+///
+/// Each branch ends as described for [`integrate`], with `termination`
+/// started afresh, so `events` that terminate only end their own branch.
+/// The sign of `step_size` is ignored.
+pub fn integrate_both<S: Stepper + ?Sized>(
+    start: Point,
+    step_size: f64,
+    backward: &mut S,
+    forward: &mut S,
+    mut termination: impl Termination,
+    events: &[Event],
+    derivative: impl Fn(f64, &[f64], &mut [f64]),
+) -> Trajectory {
+    let step_size = step_size.abs();
+    let before = integrate(
+        start.clone(),
+        -step_size,
+        backward,
+        &mut termination,
+        events,
+        &derivative,
+    );
+    let after = integrate(
+        start,
+        step_size,
+        forward,
+        &mut termination,
+        events,
+        &derivative,
+    );
+
+    stitch(before, after)
+}
+
+/// Joins the backward branch `before` and the forward branch `after` of a
+/// trajectory into one ordered by increasing x.
+pub(crate) fn stitch(before: Trajectory, after: Trajectory) -> Trajectory {
+    // both branches begin at the initial point, which is kept once
+    let mut points = before.points;
+    points.reverse();
+    let shared = usize::from(!points.is_empty());
+    points.extend(after.points.into_iter().skip(shared));
+
+    let mut crossings = before.crossings;
+    crossings.reverse();
+    crossings.extend(after.crossings);
+
+    Trajectory {
+        points,
+        crossings,
+        stop: after.stop,
+        backward_stop: Some(before.stop),
+        steps: before.steps + after.steps,
+        elapsed: before.elapsed + after.elapsed,
+    }
+}
+
+/// Integrates as described for [`integrate`], pushing the continuous
+/// extension of every step onto `extensions` when given.
+pub(crate) fn advance<S: Stepper + ?Sized>(
+    start: Point,
+    step_size: f64,
+    stepper: &mut S,
+    termination: impl Termination,
+    events: &[Event],
+    derivative: impl Fn(f64, &[f64], &mut [f64]),
+    extensions: Option<&mut Vec<Option<Continuous>>>,
+) -> Trajectory {
+    let started = Instant::now();
+    let mut trajectory = run(
+        start,
+        step_size,
+        stepper,
+        termination,
+        events,
+        derivative,
+        extensions,
+    );
+    trajectory.elapsed = started.elapsed();
+    trajectory
+}
+
+fn run<S: Stepper + ?Sized>(
+    start: Point,
+    step_size: f64,
+    stepper: &mut S,
+    mut termination: impl Termination,
+    events: &[Event],
+    derivative: impl Fn(f64, &[f64], &mut [f64]),
+    mut extensions: Option<&mut Vec<Option<Continuous>>>,
+) -> Trajectory {
+    let mut trajectory = Trajectory {
+        points: vec![],
+        crossings: vec![],
+        stop: Stop::Degenerate { x: start.x },
+        backward_stop: None,
+        steps: 0,
+        elapsed: Duration::ZERO,
+    };
+    if start.is_degenerate() {
+        return trajectory;
+    }
+
+    termination.start(&start);
+    let initial = Progress {
+        previous: &start,
+        point: &start,
+        steps: 0,
+        derivative: &derivative,
+    };
+    if let Some(reached) = termination.check(&initial) {
+        trajectory.stop = Stop::Condition(reached.reason);
+        return trajectory;
+    }
+
+    let events: Vec<&Event> = events.iter().collect();
+    let mut current = start;
+    for steps in 1.. {
+        let previous = current.clone();
+        trajectory.points.push(previous.clone());
+
+        stepper.step(&derivative, &mut current, step_size);
+        if current.is_degenerate() {
+            // adaptive steppers choose their own step, which only fails to
+            // show in x when x itself blew up
+            let x = if is_degenerate(current.x) {
+                previous.x + step_size
+            } else {
+                current.x
+            };
+            trajectory.stop = Stop::Degenerate { x };
+            return trajectory;
+        }
+        trajectory.steps = steps;
+        if let Some(extensions) = extensions.as_deref_mut() {
+            extensions.push(stepper.continuous());
+        }
+
+        let progress = Progress {
+            previous: &previous,
+            point: &current,
+            steps,
+            derivative: &derivative,
+        };
+        let reached = termination.check(&progress).map(|reached| {
+            let end = reached
+                .boundary
+                .and_then(|function| {
+                    // the boundary rises along the step, which falls in x
+                    // when integrating backwards
+                    let boundary = Event {
+                        function,
+                        direction: Direction::Both,
+                        action: Action::Terminate,
+                    };
+                    event::crossings(&[&boundary], &derivative, &previous, &current).pop()
+                })
+                .map_or_else(|| current.clone(), |crossing| crossing.point);
+            (reached.reason, end)
+        });
+
+        let along = |point: &Point| (point.x - previous.x).abs();
+        for crossing in event::crossings(&events, &derivative, &previous, &current) {
+            if reached
+                .as_ref()
+                .is_some_and(|(_, end)| along(&crossing.point) > along(end))
+            {
+                break;
+            }
+
+            let index = crossing.event;
+            if events[index].action == Action::Terminate {
+                trajectory.points.push(crossing.point.clone());
+                trajectory.crossings.push(crossing);
+                trajectory.stop = Stop::Event(index);
+                return trajectory;
+            }
+            trajectory.crossings.push(crossing);
+        }
+
+        if let Some((reason, end)) = reached {
+            trajectory.points.push(end);
+            trajectory.stop = Stop::Condition(reason);
+            return trajectory;
+        }
+    }
+
+    unreachable!("a trajectory cannot take more steps than fit in usize")
+}
+
+/// Integrates the system `y' = derivative(x, y)` from `start` until
+/// `termination` holds, see [`integrate`].
+pub fn create_dataset<S: Stepper + ?Sized>(
+    start: Point,
+    step_size: f64,
+    stepper: &mut S,
+    termination: impl Termination,
+    derivative: impl Fn(f64, &[f64], &mut [f64]),
+) -> Vec<Point> {
+    integrate(start, step_size, stepper, termination, &[], derivative).points
+}
+
+/// Fixtures shared by the tests of every module.
+#[cfg(test)]
+mod test_support {
+    use crate::{create_dataset, scalar, EndCondition, Point, Stepper};
+
+    /// The largest error of component 0 against the exact solution.
+    pub fn max_error(points: &[Point], exact: impl Fn(f64) -> f64) -> f64 {
+        points
+            .iter()
+            .map(|p| (p.y[0] - exact(p.x)).abs())
+            .fold(0.0, f64::max)
+    }
+
+    /// How much the error on `[0, 2]` drops from `step_size` to half of it,
+    /// roughly 2^order, with a new stepper for each run.
+    pub fn convergence_ratio(stepper: impl Fn() -> Box<dyn Stepper>, step_size: f64) -> f64 {
+        // y = sin(x) + 2 cos(x) solves y' = -y + 3 cos(x) - sin(x), y(0) = 2
+        let exact = |x: f64| x.sin() + 2.0 * x.cos();
+        let error = |step_size: f64| {
+            let points = create_dataset(
+                (0.0, 2.0).into(),
+                step_size,
+                stepper().as_mut(),
+                EndCondition::until(2.0),
+                scalar(|x, y| -y + 3.0 * x.cos() - x.sin()),
+            );
+            max_error(&points, exact)
+        };
+
+        error(step_size) / error(step_size / 2.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::test_support::{convergence_ratio, max_error};
+
+    fn solve(method: Method, step_size: f64, f: impl Fn(f64, f64) -> f64, y0: f64) -> Vec<Point> {
+        let end_condition = EndCondition::until(2.0);
+
+        let mut stepper = method.stepper();
+        create_dataset(
+            (0.0, y0).into(),
+            step_size,
+            stepper.as_mut(),
+            end_condition,
+            scalar(f),
+        )
+    }
+
+    #[test]
+    fn exponential_growth() {
+        let exact = f64::exp;
+        let euler = solve(Method::Euler, 0.001, |_, y| y, 1.0);
+        let rk4 = solve(Method::RungeKutta4, 0.05, |_, y| y, 1.0);
+
+        assert!(max_error(&euler, exact) < 1e-2);
+        assert!(max_error(&rk4, exact) < 1e-5);
+        assert!(max_error(&rk4, exact) < max_error(&euler, exact));
+    }
+
+    #[test]
+    fn gaussian_decay() {
+        let exact = |x: f64| (-x * x).exp();
+        let euler = solve(Method::Euler, 0.001, |x, y| -2.0 * x * y, 1.0);
+        let rk4 = solve(Method::RungeKutta4, 0.05, |x, y| -2.0 * x * y, 1.0);
+
+        assert!(max_error(&euler, exact) < 1e-3);
+        assert!(max_error(&rk4, exact) < 1e-5);
+        assert!(max_error(&rk4, exact) < max_error(&euler, exact));
+    }
+
+    #[test]
+    fn backwards() {
+        let end_condition = EndCondition {
+            max_x: None,
+            min_x: Some(-1.0),
+            max_abs_y: None,
+        };
+        let mut stepper = Method::from_name("rk45", Tolerance::default())
+            .unwrap()
+            .stepper();
+        let points = create_dataset(
+            (0.0, 1.0).into(),
+            -0.1,
+            stepper.as_mut(),
+            end_condition,
+            scalar(|_, y| y),
+        );
+
+        assert!(points.windows(2).all(|pair| pair[1].x < pair[0].x));
+        let last = points.last().unwrap();
+        assert!((last.x + 1.0).abs() < 1e-12);
+        assert!((last.y[0] - (-1f64).exp()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn both_ways() {
+        let end_condition = EndCondition {
+            max_x: Some(2.0),
+            min_x: Some(-2.0),
+            max_abs_y: None,
+        };
+        let events = [
+            Event::new(|x, _| x - 0.5, Direction::Rising, Action::Record),
+            Event::new(|x, _| x + 0.5, Direction::Rising, Action::Record),
+            Event::new(|x, _| x + 1.0, Direction::Falling, Action::Record),
+        ];
+        let trajectory = integrate_both(
+            (0.0, 1.0).into(),
+            0.05,
+            &mut RungeKutta4::default(),
+            &mut RungeKutta4::default(),
+            end_condition,
+            &events,
+            scalar(|x, y| -2.0 * x * y),
+        );
+
+        let points = &trajectory.points;
+        assert!(points.windows(2).all(|pair| pair[1].x > pair[0].x));
+        assert!((points[0].x + 2.0).abs() < 1e-12);
+        assert!((points.last().unwrap().x - 2.0).abs() < 1e-12);
+        assert!(max_error(points, |x| (-x * x).exp()) < 1e-5);
+        assert_eq!(trajectory.steps + 1, points.len());
+
+        // rising and falling refer to increasing x on both branches
+        let crossings: Vec<_> = trajectory
+            .crossings
+            .iter()
+            .map(|c| (c.event, c.rising))
+            .collect();
+        assert_eq!(crossings, [(1, true), (0, true)]);
+        assert!(matches!(
+            trajectory.backward_stop,
+            Some(Stop::Condition(ref reason)) if reason == "x reached -2"
+        ));
+    }
+
+    #[test]
+    fn order_of_convergence() {
+        // halving the step should cut the error by roughly 2^order
+        let ratio = |method: Method| convergence_ratio(|| method.stepper(), 0.1);
+        assert!(ratio(Method::Euler) > 1.8);
+        assert!(ratio(Method::Heun) > 3.5);
+        assert!(ratio(Method::Midpoint) > 3.5);
+        assert!(ratio(Method::RungeKutta4) > 12.0);
+    }
+
+    #[test]
+    fn custom_stepper() {
+        struct Exact;
+
+        impl Stepper for Exact {
+            fn step(&mut self, _: &Derivative, current: &mut Point, step_size: f64) {
+                current.y[0] *= step_size.exp();
+                current.x += step_size;
+            }
+        }
+
+        let end_condition = EndCondition::until(1.0);
+        let points = create_dataset(
+            (0.0, 1.0).into(),
+            0.25,
+            &mut Exact,
+            end_condition,
+            scalar(|_, y| y),
+        );
+
+        assert_eq!(points.len(), 5);
+        assert!(max_error(&points, f64::exp) < 1e-12);
+    }
+
+    #[test]
+    fn harmonic_oscillator() {
+        let end_condition = EndCondition::until(10.0);
+        let points = create_dataset(
+            (0.0, [1.0, 0.0]).into(),
+            0.01,
+            &mut RungeKutta4::default(),
+            end_condition,
+            |_, y, dy| {
+                dy[0] = y[1];
+                dy[1] = -y[0];
+            },
+        );
+
+        for point in &points {
+            assert!((point.y[0] - point.x.cos()).abs() < 1e-8);
+            assert!((point.y[1] + point.x.sin()).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn ends_exactly_on_limits() {
+        let points = solve(Method::Euler, 0.3, |_, y| y, 1.0);
+        assert_eq!(points.last().unwrap().x, 2.0);
+
+        let end_condition = EndCondition {
+            max_x: None,
+            min_x: None,
+            max_abs_y: Some(3.0),
+        };
+        let points = create_dataset(
+            (0.0, 1.0).into(),
+            0.1,
+            &mut RungeKutta4::default(),
+            end_condition,
+            scalar(|_, y| y),
+        );
+        let last = points.last().unwrap();
+        assert!((last.y[0] - 3.0).abs() < 1e-12);
+        assert!((last.x - 3f64.ln()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn events() {
+        let end_condition = EndCondition::until(10.0);
+        let events = [
+            Event::new(|_, y| y[0], Direction::Both, Action::Record),
+            Event::new(|_, y| y[1], Direction::Rising, Action::Terminate),
+        ];
+        let trajectory = integrate(
+            (0.0, [1.0, 0.0]).into(),
+            0.05,
+            &mut RungeKutta4::default(),
+            end_condition,
+            &events,
+            |_, y, dy| {
+                dy[0] = y[1];
+                dy[1] = -y[0];
+            },
+        );
+
+        // y0 = cos(x) falls through zero at pi/2, and y1 = -sin(x) rises
+        // through zero at pi, where the trajectory ends
+        let crossings: Vec<_> = trajectory
+            .crossings
+            .iter()
+            .map(|c| (c.event, c.rising, c.point.x))
+            .collect();
+        let [(0, false, first), (1, true, second)] = crossings[..] else {
+            panic!("{crossings:?}");
+        };
+        assert!((first - PI / 2.0).abs() < 1e-6);
+        assert!((second - PI).abs() < 1e-6);
+        assert_eq!(trajectory.points.last().unwrap().x, second);
+        assert_eq!(trajectory.stop, Stop::Event(1));
+    }
+
+    #[test]
+    fn reports_blow_ups() {
+        // y' = y^2 with y(0) = 1 is 1 / (1 - x), which Euler overshoots into
+        // infinity soon after x = 1
+        let end_condition = EndCondition::until(2.0);
+        let trajectory = integrate(
+            (0.0, 1.0).into(),
+            0.01,
+            &mut Euler::default(),
+            end_condition,
+            &[],
+            scalar(|_, y| y * y),
+        );
+
+        let Stop::Degenerate { x } = trajectory.stop else {
+            panic!("stopped by {}", trajectory.stop);
+        };
+        let last = trajectory.last().unwrap();
+        assert!(!last.is_degenerate() && last.y[0] > 1e100);
+        assert!((x - last.x - 0.01).abs() < 1e-12 && x > 1.0 && x < 2.0);
+        assert_eq!(trajectory.steps + 1, trajectory.points.len());
+    }
+
+    #[test]
+    fn second_order_reduction() {
+        // y'' = -y with y(0) = 0, y'(0) = 1 is sin(x)
+        let end_condition = EndCondition::until(5.0);
+        let points = create_dataset(
+            (0.0, [0.0, 1.0]).into(),
+            0.01,
+            &mut RungeKutta4::default(),
+            end_condition,
+            reduce_order(|_, y| -y[0]),
+        );
+
+        for point in &points {
+            assert!((point.y[0] - point.x.sin()).abs() < 1e-8);
+            assert!((point.y[1] - point.x.cos()).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn third_order_reduction() {
+        // y''' = 6 with zero initial values is x^3
+        let end_condition = EndCondition::until(3.0);
+        let points = create_dataset(
+            (0.0, [0.0, 0.0, 0.0]).into(),
+            0.1,
+            &mut RungeKutta4::default(),
+            end_condition,
+            reduce_order(|_, _| 6.0),
+        );
+
+        let last = points.last().unwrap();
+        assert!((last.y[0] - last.x.powi(3)).abs() < 1e-9);
+        assert!((last.y[2] - 6.0 * last.x).abs() < 1e-9);
+    }
+}
@@ -1,165 +1,577 @@
-use plotters::{
-    prelude::{BitMapBackend, ChartBuilder, IntoDrawingArea},
-    series::LineSeries,
-    style::{Color, BLACK, BLUE, GREEN, RED, WHITE},
+mod cli;
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::Arc,
 };
 
 use rayon::prelude::*;
 
-fn derivative_y(x: f64, y: f64) -> f64 {
-    y.cbrt() + x
+use differential::{
+    delay::{self, DelaySystem},
+    dense, equilibrium,
+    event::Event,
+    export,
+    expr::{Expr, ExprSystem},
+    integrate, integrate_both,
+    pde::Grid,
+    phase::Plane,
+    plot::{
+        draw_animation, draw_bands, draw_datasets, draw_drift, draw_heatmap, drift, Backend,
+        Overlay, Style,
+    },
+    scenario::{Axis, Scenario},
+    shooting::{self, BoundaryValueProblem, Shooting},
+    stochastic::{ensemble, DiffusionFunction, Noise},
+    JacobianFunction, Method, Point, Stop, Trajectory,
+};
+
+use cli::{Cli, Command, EquilibriaArgs, ExportArgs, ExportFormat, PdeArgs, PlotArgs, ShootArgs};
+
+fn create_trajectories(
+    scenario: &Scenario,
+    system: &ExprSystem,
+    events: &[Event],
+    jacobian: Option<Arc<JacobianFunction<'static>>>,
+    diffusion: Option<Arc<DiffusionFunction<'static>>>,
+    history: Option<Vec<Expr>>,
+) -> Vec<Trajectory> {
+    let method = scenario.method();
+    let derivative = |x: f64, y: &[f64], dy: &mut [f64]| system.derivative(x, y, dy);
+    // the noise of each trajectory depends only on its index, not on the
+    // thread that happens to integrate it
+    let seed = scenario.noise.as_ref().map_or(0, |noise| noise.seed);
+    let new_stepper = |index| match &diffusion {
+        Some(diffusion) => method.stepper_with_noise(Noise::new(diffusion.clone(), seed, index)),
+        None => method.stepper_with_jacobian(jacobian.clone()),
+    };
+
+    scenario
+        .initial_points()
+        .into_par_iter()
+        .enumerate()
+        .map(|(index, start)| {
+            let mut stepper = new_stepper(index);
+            let (step, termination) = (scenario.step, scenario.termination());
+
+            if let Some(delay) = &scenario.delay {
+                let initial = start.y.clone();
+                let system = DelaySystem {
+                    delays: delay.delays.clone(),
+                    history: match &history {
+                        Some(history) => Box::new(move |x, y: &mut [f64]| {
+                            for (y, expression) in y.iter_mut().zip(history) {
+                                *y = expression.eval(x, &[]);
+                            }
+                        }),
+                        None => Box::new(move |_, y: &mut [f64]| y.copy_from_slice(&initial)),
+                    },
+                    derivative: Box::new(derivative),
+                };
+                return delay::integrate(
+                    start,
+                    step,
+                    stepper.as_mut(),
+                    termination,
+                    events,
+                    &system,
+                );
+            }
+
+            let Some(grid) = scenario.resample else {
+                return if scenario.bidirectional {
+                    let mut backward = new_stepper(index);
+                    integrate_both(
+                        start,
+                        step,
+                        backward.as_mut(),
+                        stepper.as_mut(),
+                        termination,
+                        events,
+                        derivative,
+                    )
+                } else {
+                    integrate(
+                        start,
+                        step,
+                        stepper.as_mut(),
+                        termination,
+                        events,
+                        derivative,
+                    )
+                };
+            };
+
+            let solution = if scenario.bidirectional {
+                let mut backward = new_stepper(index);
+                dense::integrate_both(
+                    start,
+                    step,
+                    backward.as_mut(),
+                    stepper.as_mut(),
+                    termination,
+                    events,
+                    derivative,
+                )
+            } else {
+                dense::integrate(
+                    start,
+                    step,
+                    stepper.as_mut(),
+                    termination,
+                    events,
+                    derivative,
+                )
+            };
+            let points = solution.resample(grid.values());
+            Trajectory {
+                points,
+                ..solution.trajectory
+            }
+        })
+        .collect()
 }
 
-#[derive(Debug, Default, Clone, Copy)]
-struct Point {
-    x: f64,
-    y: f64,
+/// What ended the trajectory, naming the branch when it went both ways.
+fn stopped_by(trajectory: &Trajectory) -> String {
+    match &trajectory.backward_stop {
+        Some(backward) => format!("{backward} backwards and {} forwards", trajectory.stop),
+        None => trajectory.stop.to_string(),
+    }
 }
 
-impl Into<Point> for (f64, f64) {
-    fn into(self) -> Point {
-        Point {
-            x: self.0,
-            y: self.1,
+fn solve(scenario: &Scenario, trajectories: &[Trajectory]) {
+    for (i, trajectory) in trajectories.iter().enumerate() {
+        let points = &trajectory.points;
+        match (points.first(), points.last()) {
+            (Some(first), Some(last)) => println!(
+                "trajectory {i}: y({}) = {:?} -> y({}) = {:?} after {} steps in {:?}, stopped by {}",
+                first.x,
+                first.y,
+                last.x,
+                last.y,
+                trajectory.steps,
+                trajectory.elapsed,
+                stopped_by(trajectory)
+            ),
+            _ => println!(
+                "trajectory {i}: no points, stopped by {}",
+                stopped_by(trajectory)
+            ),
+        }
+
+        for crossing in &trajectory.crossings {
+            println!(
+                "  event {} ({}, {}): y({}) = {:?}",
+                crossing.event,
+                scenario.events[crossing.event].expression,
+                if crossing.rising { "rising" } else { "falling" },
+                crossing.point.x,
+                crossing.point.y
+            );
         }
     }
 }
 
-fn decide_bounds(x_range: (f64, f64), y_range: (f64, f64)) -> (f64, f64, f64, f64) {
-    (
-        x_range.0 - 1.0,
-        x_range.1 + 1.0,
-        y_range.0 - 1.0,
-        y_range.1 + 1.0,
-    )
+/// Axes spanning the region the trajectories cover in every component, with
+/// the same padding as the chart bounds.
+fn covered_region(datasets: &[Vec<Point>], dimension: usize, count: usize) -> Vec<Axis> {
+    (0..dimension)
+        .map(|i| {
+            let values = datasets.iter().flatten().map(|point| point.y[i]);
+            let from = values.clone().fold(f64::INFINITY, f64::min);
+            let to = values.fold(f64::NEG_INFINITY, f64::max);
+            Axis {
+                from: from - 1.0,
+                to: to + 1.0,
+                count,
+            }
+        })
+        .collect()
 }
 
-fn is_degenerate(x: f64) -> bool {
-    match x.classify() {
-        std::num::FpCategory::Nan => true,
-        std::num::FpCategory::Infinite => true,
-        _ => false,
+fn equilibria(
+    args: &EquilibriaArgs,
+    scenario: &Scenario,
+    system: &ExprSystem,
+    datasets: &[Vec<Point>],
+) -> io::Result<()> {
+    if scenario.delay.is_some() {
+        fail("the stability of a delay differential equation depends on its past, not only its state");
+    }
+
+    let derivative = |x: f64, y: &[f64], dy: &mut [f64]| system.derivative(x, y, dy);
+    let region = covered_region(datasets, scenario.dimension(), args.seeds as usize);
+    let mut equilibria = equilibrium::find(&derivative, scenario.start_x(), Axis::grid(&region));
+    // Newton may converge onto equilibria far from where it started
+    equilibria.retain(|equilibrium| {
+        region
+            .iter()
+            .zip(&equilibrium.y)
+            .all(|(axis, y)| (axis.from..=axis.to).contains(y))
+    });
+
+    let mut output = io::stdout().lock();
+    if args.json {
+        serde_json::to_writer_pretty(&mut output, &equilibria)?;
+        return writeln!(output);
+    }
+
+    if equilibria.is_empty() {
+        writeln!(output, "no equilibria found")?;
     }
+    for (i, equilibrium) in equilibria.iter().enumerate() {
+        let eigenvalues: Vec<String> = equilibrium
+            .eigenvalues
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        writeln!(
+            output,
+            "equilibrium {i}: y = {:?}, {}, eigenvalues [{}]",
+            equilibrium.y,
+            equilibrium.classification,
+            eigenvalues.join(", ")
+        )?;
+    }
+
+    Ok(())
 }
 
-struct EndCondition {
-    max_x: Option<f64>,
-    max_abs_y: Option<f64>,
+fn plot(
+    args: &PlotArgs,
+    scenario: &Scenario,
+    system: &ExprSystem,
+    datasets: &[Vec<Point>],
+    stops: &[Stop],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut settings = scenario.plot.clone();
+    args.apply(&mut settings);
+    if let Err(e) = settings.validate(scenario.dimension()) {
+        fail(e);
+    }
+
+    if let Some(conserved) = settings
+        .conserved(scenario.dimension())
+        .unwrap_or_else(|e| fail(e))
+    {
+        let drifts = drift(datasets, |x, y| conserved.eval(x, y));
+        let label = settings.conserved.as_deref().unwrap_or_default();
+        return draw_drift(
+            &settings.output,
+            settings.backend(),
+            &settings.style,
+            &drifts,
+            stops,
+            label,
+        );
+    }
+
+    if let Some(bands) = settings.ensemble {
+        let Some(noise) = &scenario.noise else {
+            fail("the ensemble bands need a stochastic run to take sample paths from");
+        };
+        let ensembles: Vec<_> = datasets
+            .chunks(noise.paths)
+            .map(|paths| ensemble(paths, bands.lower, bands.upper))
+            .collect();
+        return draw_bands(
+            &settings.output,
+            settings.backend(),
+            &settings.style,
+            &ensembles,
+            scenario.dimension(),
+            (bands.lower, bands.upper),
+        );
+    }
+
+    let overlay_requested = settings.direction_field.is_some() || settings.phase_portrait.is_some();
+    if overlay_requested && scenario.delay.is_some() {
+        fail("the slope of a delay differential equation depends on its past, so it has no direction field or phase portrait");
+    }
+
+    let derivative = |x: f64, y: &[f64], dy: &mut [f64]| system.derivative(x, y, dy);
+    let overlay = if let Some(field) = &settings.direction_field {
+        Some(Overlay::DirectionField(field, &derivative))
+    } else {
+        settings.phase_portrait.as_ref().map(|portrait| {
+            let plane = Plane {
+                derivative: &derivative,
+                x: scenario.start_x(),
+                pair: settings.pairs[0],
+            };
+            Overlay::PhasePortrait(portrait, plane)
+        })
+    };
+
+    draw_datasets(
+        &settings.output,
+        settings.backend(),
+        &settings.style,
+        datasets,
+        stops,
+        &settings.projections(scenario.dimension()),
+        overlay,
+    )
 }
 
-impl EndCondition {
-    fn has_reached(&self, current: &Point) -> bool {
-        if self.max_x.map_or(false, |max_x| current.x > max_x) {
-            true
-        } else if self
-            .max_abs_y
-            .map_or(false, |max_y| current.y.abs() > max_y)
-        {
-            true
-        } else {
-            false
+fn open(path: &Path) -> io::Result<BufWriter<Box<dyn Write>>> {
+    let output: Box<dyn Write> = if path.as_os_str() == "-" {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(path)?)
+    };
+
+    Ok(BufWriter::new(output))
+}
+
+fn export(args: &ExportArgs, scenario: &Scenario, datasets: &[Vec<Point>]) -> io::Result<()> {
+    if args.per_trajectory {
+        let stem = args
+            .output
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let extension = args.output.extension().unwrap_or("csv".as_ref());
+
+        for (i, points) in datasets.iter().enumerate() {
+            let path = args
+                .output
+                .with_file_name(format!("{stem}_{i}"))
+                .with_extension(extension);
+
+            let mut output = open(&path)?;
+            export::write_trajectory_csv(&mut output, scenario, i, points)?;
+            output.flush()?;
+        }
+
+        return Ok(());
+    }
+
+    let mut output = open(&args.output)?;
+    match args.format() {
+        ExportFormat::Csv => export::write_long_csv(&mut output, scenario, datasets)?,
+        ExportFormat::Json => export::write_json(&mut output, scenario, datasets)?,
+    }
+    output.flush()
+}
+
+fn shoot(
+    args: &ShootArgs,
+    scenario: &Scenario,
+    system: &ExprSystem,
+    jacobian: Option<Arc<JacobianFunction<'static>>>,
+) -> io::Result<()> {
+    if scenario.noise.is_some() || scenario.delay.is_some() {
+        fail("shooting needs an ordinary differential equation, without noise or delays");
+    }
+    if scenario.integrates_backwards() {
+        fail("shooting integrates towards increasing x, with a positive step");
+    }
+
+    let guess = scenario.initial_points().swap_remove(0);
+    let problem = BoundaryValueProblem {
+        interval: [guess.x, args.to],
+        start: args.at_start.clone(),
+        end: args.at_end.clone(),
+        guess: guess.y,
+    };
+    let settings = Shooting {
+        step_size: scenario.step,
+        segments: args.segments as usize,
+        tolerance: args.tolerance,
+        max_iterations: args.max_iterations,
+    };
+    let mut stepper = scenario.method().stepper_with_jacobian(jacobian);
+    let derivative = |x: f64, y: &[f64], dy: &mut [f64]| system.derivative(x, y, dy);
+    let solution = shooting::shoot(&problem, &settings, stepper.as_mut(), derivative)
+        .unwrap_or_else(|e| fail(e));
+
+    if let Some(path) = &args.output {
+        let mut output = open(path)?;
+        export::write_solution_csv(&mut output, scenario, &problem, &solution.points)?;
+        output.flush()?;
+        if path.as_os_str() == "-" {
+            return Ok(());
         }
     }
+
+    let (first, last) = (
+        &solution.points[0],
+        &solution.points[solution.points.len() - 1],
+    );
+    println!(
+        "converged after {} iteration(s) with a mismatch of {}: y({}) = {:?} -> y({}) = {:?}",
+        solution.iterations, solution.mismatch, first.x, first.y, last.x, last.y
+    );
+    Ok(())
 }
 
-fn create_dataset(
-    start: Point,
-    step_size: f64,
-    end_condition: EndCondition,
-    derivative_y: impl Fn(f64, f64) -> f64,
-) -> Vec<(f64, f64)> {
-    let mut current = start;
+fn pde(args: &PdeArgs, scenario: &Scenario) -> Result<(), Box<dyn std::error::Error>> {
+    if scenario.noise.is_some() || scenario.delay.is_some() || scenario.jacobian.is_some() {
+        fail("the method of lines sets up its own equations, without noise, delays or a Jacobian");
+    }
+    if scenario.integrates_backwards() {
+        fail("the method of lines integrates forwards in time, with a positive step");
+    }
 
-    let mut points = vec![];
+    let Some(boundaries) = args.boundaries() else {
+        fail("--left and --right must both be periodic or neither");
+    };
+    let grid = Grid {
+        from: args.domain.from,
+        to: args.domain.to,
+        points: args.domain.count,
+        boundaries,
+    };
+    let parse = |source: &str, dimension, option| {
+        Expr::parse_with_dimension(source, dimension)
+            .unwrap_or_else(|e| fail(format!("{option}: {}", e.annotate(source))))
+    };
+    let initial = parse(&args.initial, 0, "--initial");
+    let reaction = parse(&args.reaction, 1, "--reaction");
 
-    while !end_condition.has_reached(&current)
-        && !is_degenerate(current.x)
-        && !is_degenerate(current.y)
+    let method = scenario.method();
+    let limit = grid.spacing().powi(2) / (2.0 * args.diffusivity);
+    if !method.is_implicit() && !matches!(method, Method::DormandPrince(_)) && scenario.step > limit
     {
-        points.push((current.x, current.y));
+        eprintln!(
+            "warning: explicit steps much above dx^2 / (2 D) = {limit} blow up on this grid; an implicit method such as bdf2 takes any step"
+        );
+    }
 
-        current.y += derivative_y(current.x, current.y) * step_size;
-        current.x += step_size;
+    let start = Point {
+        x: scenario.start_x(),
+        y: grid.initial(|x| initial.eval(x, &[])),
+    };
+    let trajectory = integrate(
+        start,
+        scenario.step,
+        method.stepper().as_mut(),
+        scenario.termination(),
+        &[],
+        grid.derivative(args.diffusivity, |x, u| reaction.eval(x, &[u])),
+    );
+    if let Stop::Degenerate { .. } = trajectory.stop {
+        eprintln!("warning: the solution stopped by {}", trajectory.stop);
     }
 
-    points
+    let style = Style {
+        caption: Some(match args.reaction.trim() {
+            "0" => format!("u_t = {} u_xx", args.diffusivity),
+            reaction => format!("u_t = {} u_xx + {reaction}", args.diffusivity),
+        }),
+        ..scenario.plot.style.clone()
+    };
+    let positions = grid.positions();
+    if args
+        .output
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("gif"))
+    {
+        // a frame every 50 ms
+        draw_animation(
+            &args.output,
+            &style,
+            &positions,
+            &trajectory.points,
+            args.frames as usize,
+            50,
+        )
+    } else {
+        draw_heatmap(
+            &args.output,
+            Backend::from_path(&args.output),
+            &style,
+            &positions,
+            &trajectory.points,
+        )
+    }
+}
+
+fn fail(error: impl fmt::Display) -> ! {
+    eprintln!("error: {error}");
+    std::process::exit(1);
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let start: Point = (0.0, 5.0).into();
+    let cli = Cli::parse_and_validate();
 
-    let delta = 0.001;
+    let scenario = match &cli.scenario {
+        Some(path) => Scenario::load(path),
+        None => {
+            let scenario = cli.run.scenario();
+            scenario.validate().map(|_| scenario)
+        }
+    };
+    let (scenario, system) = match scenario.and_then(|s| s.system().map(|system| (s, system))) {
+        Ok(loaded) => loaded,
+        Err(e) => fail(e),
+    };
 
-    let start_x = 0.0;
-    let y_spread = 10.0;
-    let num_datasets = 10;
+    let events = scenario.events().unwrap_or_else(|e| fail(e));
+    let jacobian = scenario.jacobian().unwrap_or_else(|e| fail(e));
+    let diffusion = scenario.diffusion().unwrap_or_else(|e| fail(e));
+    let history = scenario.history().unwrap_or_else(|e| fail(e));
 
-    let datasets: Vec<_> = (0..num_datasets)
-        .into_par_iter()
-        .map(|i| {
-            let end_condition = EndCondition {
-                max_x: Some(150.0),
-                max_abs_y: Some(150.0),
-            };
+    match &cli.command {
+        Some(Command::Shoot(args)) => return Ok(shoot(args, &scenario, &system, jacobian)?),
+        Some(Command::Pde(args)) => return pde(args, &scenario),
+        _ => {}
+    }
 
-            let start = (start_x, 0.0 + i as f64 * y_spread).into();
-            create_dataset(start, delta, end_condition, derivative_y)
+    let trajectories =
+        create_trajectories(&scenario, &system, &events, jacobian, diffusion, history);
+    let command = match cli.command {
+        Some(Command::Solve) => {
+            solve(&scenario, &trajectories);
+            return Ok(());
+        }
+        command => command,
+    };
+
+    // blow-ups would otherwise only show as curves ending early
+    for (i, trajectory) in trajectories.iter().enumerate() {
+        let ends = [trajectory.points.first(), trajectory.last()];
+        let ends = if trajectory.backward_stop.is_some() {
+            &ends[..]
+        } else {
+            &ends[1..]
+        };
+        for (stop, end) in trajectory.stops().zip(ends) {
+            if let Stop::Degenerate { .. } = stop {
+                let end = end.map_or(String::new(), |end| {
+                    format!(", ending at y({}) = {:?}", end.x, end.y)
+                });
+                eprintln!("warning: trajectory {i} stopped by {stop}{end}");
+            }
+        }
+    }
+
+    let (datasets, stops): (Vec<Vec<Point>>, Vec<Stop>) = trajectories
+        .into_iter()
+        .map(|trajectory| {
+            let blow_up = trajectory
+                .stops()
+                .find(|stop| matches!(stop, Stop::Degenerate { .. }))
+                .cloned();
+            (trajectory.points, blow_up.unwrap_or(trajectory.stop))
         })
-        .collect();
-
-    let max_x = datasets
-        .iter()
-        .flatten()
-        .map(|a| a.0)
-        .reduce(f64::max)
-        .unwrap();
-
-    let min_y = datasets
-        .iter()
-        .flatten()
-        .map(|a| a.1)
-        .reduce(f64::min)
-        .unwrap();
-
-    let max_y = datasets
-        .iter()
-        .flatten()
-        .map(|a| a.1)
-        .reduce(f64::max)
-        .unwrap();
-
-    let (left_bound, right_bound, bottom_bound, top_bound) =
-        decide_bounds((start_x, max_x), (min_y, max_y));
-
-    let root = BitMapBackend::new("output.png", (1280, 960)).into_drawing_area();
-    root.fill(&WHITE)?;
-
-    let mut chart = ChartBuilder::on(&root)
-        .margin(5)
-        .x_label_area_size(30)
-        .y_label_area_size(30)
-        .build_cartesian_2d(left_bound..right_bound, bottom_bound..top_bound)?;
-
-    chart.configure_mesh().draw()?;
-
-    let colors = vec![&RED, &BLACK, &BLUE, &GREEN];
-
-    for (i, points) in datasets.iter().enumerate() {
-        chart
-            .draw_series(LineSeries::new(
-                points.iter().copied(),
-                colors[i % colors.len()],
-            ))?
-            .label("graph");
-    }
-
-    chart
-        .configure_series_labels()
-        .background_style(&WHITE.mix(0.8))
-        .border_style(&BLACK)
-        .draw()?;
-
-    root.present()?;
+        .unzip();
+    match command {
+        Some(Command::Plot(args)) => plot(&args, &scenario, &system, &datasets, &stops)?,
+        Some(Command::Export(args)) => export(&args, &scenario, &datasets)?,
+        Some(Command::Equilibria(args)) => equilibria(&args, &scenario, &system, &datasets)?,
+        Some(Command::Shoot(_) | Command::Pde(_)) => {
+            unreachable!("shooting and the method of lines set up their own integration")
+        }
+        Some(Command::Solve) | None => {
+            plot(&PlotArgs::default(), &scenario, &system, &datasets, &stops)?
+        }
+    }
 
     Ok(())
 }
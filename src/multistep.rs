@@ -0,0 +1,180 @@
+//! Linear multistep methods, which reuse the derivatives of past steps.
+//!
+//! The Adams–Bashforth–Moulton pair of order `k` needs the derivatives at
+//! the last `k` points, so the first steps of a trajectory are taken with
+//! classical Runge–Kutta until enough have been collected. From then on a
+//! step costs two evaluations of the derivative whatever the order.
+
+use std::collections::VecDeque;
+
+use crate::{stepper::combine, Derivative, Point, RungeKutta4, Stepper};
+
+// y_{n+1} = y_n + h sum_j BASHFORTH[k][j] f_{n-j}, order k + 1
+const BASHFORTH: [&[f64]; 5] = [
+    &[1.0],
+    &[3.0 / 2.0, -1.0 / 2.0],
+    &[23.0 / 12.0, -16.0 / 12.0, 5.0 / 12.0],
+    &[55.0 / 24.0, -59.0 / 24.0, 37.0 / 24.0, -9.0 / 24.0],
+    &[
+        1901.0 / 720.0,
+        -2774.0 / 720.0,
+        2616.0 / 720.0,
+        -1274.0 / 720.0,
+        251.0 / 720.0,
+    ],
+];
+
+// y_{n+1} = y_n + h sum_j MOULTON[k][j] f_{n+1-j}, order k + 1
+const MOULTON: [&[f64]; 5] = [
+    &[1.0],
+    &[1.0 / 2.0, 1.0 / 2.0],
+    &[5.0 / 12.0, 8.0 / 12.0, -1.0 / 12.0],
+    &[9.0 / 24.0, 19.0 / 24.0, -5.0 / 24.0, 1.0 / 24.0],
+    &[
+        251.0 / 720.0,
+        646.0 / 720.0,
+        -264.0 / 720.0,
+        106.0 / 720.0,
+        -19.0 / 720.0,
+    ],
+];
+
+/// Adams–Bashforth prediction and Adams–Moulton correction in PECE mode
+/// (predict, evaluate, correct, evaluate), of order 1 to 5.
+///
+/// A step size different from the last, or a state other than the last one
+/// produced, starts over with Runge–Kutta steps.
+#[derive(Debug, Clone)]
+pub struct AdamsBashforthMoulton {
+    pub order: usize,
+    // derivatives at the last points, most recent first
+    history: VecDeque<Vec<f64>>,
+    // the last point produced and the step size that led to it
+    last: Option<(Point, f64)>,
+    start_up: RungeKutta4,
+    tmp: Vec<f64>,
+}
+
+impl AdamsBashforthMoulton {
+    /// `order` is clamped to 1 to 5.
+    pub fn new(order: usize) -> Self {
+        AdamsBashforthMoulton {
+            order: order.clamp(1, BASHFORTH.len()),
+            history: VecDeque::new(),
+            last: None,
+            start_up: RungeKutta4::default(),
+            tmp: vec![],
+        }
+    }
+}
+
+impl Stepper for AdamsBashforthMoulton {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let (x, h) = (current.x, step_size);
+        let continues =
+            matches!(&self.last, Some((point, last_h)) if point == current && *last_h == h);
+        if !continues {
+            let mut slope = vec![0.0; current.y.len()];
+            derivative(x, &current.y, &mut slope);
+            self.history.clear();
+            self.history.push_front(slope);
+        }
+
+        if self.history.len() < self.order {
+            self.start_up.step(derivative, current, h);
+        } else {
+            let order = self.order;
+            let stages: Vec<(f64, &[f64])> = BASHFORTH[order - 1]
+                .iter()
+                .zip(&self.history)
+                .map(|(&weight, slope)| (weight, slope.as_slice()))
+                .collect();
+            combine(&mut self.tmp, &current.y, h, &stages);
+
+            let mut predicted = vec![0.0; current.y.len()];
+            derivative(x + h, &self.tmp, &mut predicted);
+
+            let (&newest, rest) = MOULTON[order - 1].split_first().unwrap_or((&1.0, &[]));
+            let mut stages: Vec<(f64, &[f64])> = vec![(newest, &predicted)];
+            stages.extend(
+                rest.iter()
+                    .zip(&self.history)
+                    .map(|(&weight, slope)| (weight, slope.as_slice())),
+            );
+            combine(&mut self.tmp, &current.y, h, &stages);
+            std::mem::swap(&mut current.y, &mut self.tmp);
+            current.x = x + h;
+        }
+
+        // reuse the oldest buffer for the derivative at the new point
+        let mut slope = if self.history.len() >= self.order {
+            self.history.pop_back().unwrap_or_default()
+        } else {
+            vec![0.0; current.y.len()]
+        };
+        derivative(current.x, &current.y, &mut slope);
+        self.history.push_front(slope);
+        self.last = Some((current.clone(), h));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::{integrate, scalar, test_support::convergence_ratio, EndCondition, Method};
+
+    #[test]
+    fn order_of_convergence() {
+        for order in 1..=5 {
+            let ratio = convergence_ratio(|| Box::new(AdamsBashforthMoulton::new(order)), 0.04);
+            let expected = 2f64.powi(order as i32);
+            assert!(
+                (ratio / expected - 1.0).abs() < 0.2,
+                "order {order}: {ratio}"
+            );
+        }
+    }
+
+    #[test]
+    fn cheaper_than_runge_kutta() {
+        let evaluations = Cell::new(0);
+        let count = |method: Method| {
+            evaluations.set(0);
+            integrate(
+                (0.0, 1.0).into(),
+                0.01,
+                method.stepper().as_mut(),
+                EndCondition::until(150.0),
+                &[],
+                scalar(|_, y| {
+                    evaluations.set(evaluations.get() + 1);
+                    -y
+                }),
+            );
+            evaluations.get()
+        };
+
+        let adams = count(Method::AdamsBashforthMoulton(4));
+        let runge_kutta = count(Method::RungeKutta4);
+        // two evaluations a step against four
+        let ratio = adams as f64 / runge_kutta as f64;
+        assert!((ratio - 0.5).abs() < 0.01, "{adams} vs {runge_kutta}");
+    }
+
+    #[test]
+    fn restarts_on_a_new_step_size() {
+        let mut stepper = AdamsBashforthMoulton::new(3);
+        let f = scalar(|_, y| y);
+        let mut point: Point = (0.0, 1.0).into();
+        for _ in 0..5 {
+            stepper.step(&f, &mut point, 0.1);
+        }
+        assert_eq!(stepper.history.len(), 3);
+
+        stepper.step(&f, &mut point, 0.05);
+        assert_eq!(stepper.history.len(), 2);
+        assert!((point.y[0] - 0.55f64.exp()).abs() < 1e-3);
+    }
+}
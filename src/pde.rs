@@ -0,0 +1,222 @@
+//! Parabolic equations `u_t = D u_xx + f(x, u)` in one space dimension,
+//! solved by the method of lines.
+//!
+//! Space is discretized on an evenly spaced grid, and the second
+//! derivative at each point is replaced by its central difference, leaving
+//! one ordinary differential equation per grid point. The integrators then
+//! march these in time: their `x` is the time `t`, and component `i` of
+//! their `y` is `u` at the `i`th position of the grid.
+//!
+//! The equations are stiff, the fastest decaying at a rate of about
+//! `4 D / dx^2`, so explicit methods need steps below about `dx^2 / (2 D)`
+//! while the implicit ones take any step.
+
+use serde::{Deserialize, Serialize};
+
+/// The condition at one end of the domain.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Boundary {
+    /// `u` is held at this value.
+    Dirichlet(f64),
+    /// `u_x` is held at this value, so 0 lets nothing flow through the end.
+    Neumann(f64),
+}
+
+/// The conditions at both ends of the domain.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Boundaries {
+    /// The ends are joined, as on a ring.
+    Periodic,
+    /// A condition at each end, the left one first.
+    Ends(Boundary, Boundary),
+}
+
+/// An evenly spaced grid of `points` positions over the domain from `from`
+/// to `to`, with its boundary conditions. The positions include both ends,
+/// except on a periodic domain, where `to` is the same point as `from`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    pub from: f64,
+    /// Beyond `from`.
+    pub to: f64,
+    /// At least 3.
+    pub points: usize,
+    pub boundaries: Boundaries,
+}
+
+impl Grid {
+    /// The distance between neighbouring positions.
+    pub fn spacing(&self) -> f64 {
+        let intervals = match self.boundaries {
+            Boundaries::Periodic => self.points,
+            Boundaries::Ends(..) => self.points - 1,
+        };
+        (self.to - self.from) / intervals as f64
+    }
+
+    pub fn positions(&self) -> Vec<f64> {
+        let spacing = self.spacing();
+        (0..self.points)
+            .map(|i| self.from + spacing * i as f64)
+            .collect()
+    }
+
+    /// The state sampling `profile` at every position, with the ends held
+    /// by a Dirichlet condition at their value instead.
+    pub fn initial(&self, profile: impl Fn(f64) -> f64) -> Vec<f64> {
+        let mut u: Vec<f64> = self.positions().into_iter().map(profile).collect();
+        if let Boundaries::Ends(left, right) = self.boundaries {
+            let last = u.len() - 1;
+            for (i, boundary) in [(0, left), (last, right)] {
+                if let Boundary::Dirichlet(value) = boundary {
+                    u[i] = value;
+                }
+            }
+        }
+        u
+    }
+
+    /// The right-hand side of `u_t = diffusivity u_xx + reaction(x, u)` at
+    /// every position, for the integrators. The ends held by a Dirichlet
+    /// condition do not change.
+    pub fn derivative<'a>(
+        &self,
+        diffusivity: f64,
+        reaction: impl Fn(f64, f64) -> f64 + 'a,
+    ) -> impl Fn(f64, &[f64], &mut [f64]) + 'a {
+        let (positions, spacing, boundaries) = (self.positions(), self.spacing(), self.boundaries);
+        let scale = diffusivity / (spacing * spacing);
+
+        move |_, u, du| {
+            let last = u.len() - 1;
+            for i in 0..=last {
+                // the neighbours, beyond the ends through the boundaries
+                let (left, right) = match boundaries {
+                    Boundaries::Periodic => (u[(i + last) % u.len()], u[(i + 1) % u.len()]),
+                    Boundaries::Ends(left, right) => {
+                        let left = match (i, left) {
+                            (0, Boundary::Dirichlet(_)) => {
+                                du[i] = 0.0;
+                                continue;
+                            }
+                            // the mirror image of u[1] that makes the
+                            // central difference at 0 equal to the slope
+                            (0, Boundary::Neumann(slope)) => u[1] - 2.0 * spacing * slope,
+                            _ => u[i - 1],
+                        };
+                        let right = match (i == last, right) {
+                            (true, Boundary::Dirichlet(_)) => {
+                                du[i] = 0.0;
+                                continue;
+                            }
+                            (true, Boundary::Neumann(slope)) => u[last - 1] + 2.0 * spacing * slope,
+                            _ => u[i + 1],
+                        };
+                        (left, right)
+                    }
+                };
+
+                du[i] = scale * (left - 2.0 * u[i] + right) + reaction(positions[i], u[i]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::{
+        integrate, test_support::max_error, EndCondition, Method, Point, RungeKutta4, Tolerance,
+    };
+
+    /// The profile at `end`, as points of position and `u`.
+    fn solve(grid: &Grid, profile: impl Fn(f64) -> f64, step_size: f64, end: f64) -> Vec<Point> {
+        let trajectory = integrate(
+            (0.0, grid.initial(profile)).into(),
+            step_size,
+            &mut RungeKutta4::default(),
+            EndCondition::until(end),
+            &[],
+            grid.derivative(1.0, |_, _| 0.0),
+        );
+        let u = &trajectory.last().unwrap().y;
+        grid.positions()
+            .into_iter()
+            .zip(u)
+            .map(|(x, &u)| (x, u).into())
+            .collect()
+    }
+
+    #[test]
+    fn dirichlet_modes_decay() {
+        // u = exp(-pi^2 t) sin(pi x) with u = 0 at both ends
+        let grid = Grid {
+            from: 0.0,
+            to: 1.0,
+            points: 41,
+            boundaries: Boundaries::Ends(Boundary::Dirichlet(0.0), Boundary::Dirichlet(0.0)),
+        };
+        let end = solve(&grid, |x| (PI * x).sin(), 1e-4, 0.1);
+        let decay = (-PI * PI * 0.1f64).exp();
+        let error = max_error(&end, |x| decay * (PI * x).sin());
+        // second order in space, dx^2 pi^2 / 12 relative
+        assert!(error < 5e-4, "{error}");
+    }
+
+    #[test]
+    fn neumann_ends_keep_the_heat_in() {
+        // u = 1 + exp(-pi^2 t) cos(pi x) with no flow through either end
+        let grid = Grid {
+            from: 0.0,
+            to: 1.0,
+            points: 41,
+            boundaries: Boundaries::Ends(Boundary::Neumann(0.0), Boundary::Neumann(0.0)),
+        };
+        let end = solve(&grid, |x| 1.0 + (PI * x).cos(), 1e-4, 0.1);
+        let decay = (-PI * PI * 0.1f64).exp();
+        let error = max_error(&end, |x| 1.0 + decay * (PI * x).cos());
+        assert!(error < 5e-4, "{error}");
+
+        // the trapezoidal total heat stays put
+        let total = |u: &[f64]| u.iter().sum::<f64>() - (u[0] + u[u.len() - 1]) / 2.0;
+        let initial = grid.initial(|x| 1.0 + (PI * x).cos());
+        let u: Vec<f64> = end.iter().map(|p| p.y[0]).collect();
+        assert!((total(&u) - total(&initial)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn periodic_wave_and_reaction() {
+        // u = exp(-4 pi^2 t) sin(2 pi x) on a ring
+        let grid = Grid {
+            from: 0.0,
+            to: 1.0,
+            points: 40,
+            boundaries: Boundaries::Periodic,
+        };
+        assert_eq!(grid.positions().len(), 40);
+        assert!((grid.spacing() - 0.025).abs() < 1e-15);
+        let end = solve(&grid, |x| (2.0 * PI * x).sin(), 1e-4, 0.05);
+        let decay = (-4.0 * PI * PI * 0.05f64).exp();
+        let error = max_error(&end, |x| decay * (2.0 * PI * x).sin());
+        assert!(error < 1e-3, "{error}");
+
+        // a flat profile only feels the reaction, here logistic growth
+        let derivative = grid.derivative(1.0, |_, u| u * (1.0 - u));
+        let trajectory = integrate(
+            (0.0, grid.initial(|_| 0.1)).into(),
+            0.5,
+            Method::Bdf(2, Tolerance::default()).stepper().as_mut(),
+            EndCondition::until(5.0),
+            &[],
+            derivative,
+        );
+        let logistic = 1.0 / (1.0 + 9.0 * (-5.0f64).exp());
+        for u in &trajectory.last().unwrap().y {
+            assert!((u - logistic).abs() < 0.02, "{u} vs {logistic}");
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! Geometry of the phase plane of a two-component autonomous system: the
+//! vector field sampled on a grid, and the nullclines where one component of
+//! the derivative vanishes.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    equilibrium::{self, Equilibrium},
+    Derivative,
+};
+
+/// Sampling of the phase plane drawn beneath the trajectories.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhasePortrait {
+    /// Columns and rows of the grid of arrows.
+    pub columns: u32,
+    pub rows: u32,
+    /// Color each arrow by the speed of the flow instead of drawing them all
+    /// in grey.
+    pub colored: bool,
+    pub nullclines: bool,
+    /// Cells along each axis of the grid the nullclines are traced on.
+    pub resolution: u32,
+    /// Mark the equilibria within the chart, labelled with their stability.
+    pub equilibria: bool,
+}
+
+impl Default for PhasePortrait {
+    fn default() -> Self {
+        PhasePortrait {
+            columns: 20,
+            rows: 20,
+            colored: false,
+            nullclines: true,
+            resolution: 200,
+            equilibria: false,
+        }
+    }
+}
+
+/// An arrow of the vector field, centered on a grid point, with its head at
+/// `to`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arrow {
+    pub from: (f64, f64),
+    pub to: (f64, f64),
+    /// Length of the vector before normalizing, relative to the longest
+    /// arrow of the field.
+    pub magnitude: f64,
+}
+
+/// A plane through a two-component system: the derivative evaluated at `x`
+/// with components `pair.0` and `pair.1` spanning the axes.
+pub struct Plane<'a> {
+    pub derivative: &'a Derivative<'a>,
+    pub x: f64,
+    pub pair: (usize, usize),
+}
+
+impl Plane<'_> {
+    fn evaluate(&self, (u, v): (f64, f64), y: &mut [f64], dy: &mut [f64]) -> (f64, f64) {
+        y[self.pair.0] = u;
+        y[self.pair.1] = v;
+        (self.derivative)(self.x, y, dy);
+        (dy[self.pair.0], dy[self.pair.1])
+    }
+
+    fn buffers(&self) -> (Vec<f64>, Vec<f64>) {
+        let dimension = self.pair.0.max(self.pair.1) + 1;
+        (vec![0.0; dimension], vec![0.0; dimension])
+    }
+
+    /// The point of the plane a state of the system lies at.
+    pub fn project(&self, y: &[f64]) -> (f64, f64) {
+        (y[self.pair.0], y[self.pair.1])
+    }
+
+    /// Finds the equilibria within `bounds`, seeding Newton's method from
+    /// the centers of the cells of the arrow grid.
+    pub fn equilibria(
+        &self,
+        portrait: &PhasePortrait,
+        bounds: (f64, f64, f64, f64),
+    ) -> Vec<Equilibrium> {
+        let (left, right, bottom, top) = bounds;
+        let cell_width = (right - left) / portrait.columns as f64;
+        let cell_height = (top - bottom) / portrait.rows as f64;
+
+        let seeds = (0..portrait.columns).flat_map(|column| {
+            (0..portrait.rows).map(move |row| {
+                let (mut y, _) = self.buffers();
+                y[self.pair.0] = left + (column as f64 + 0.5) * cell_width;
+                y[self.pair.1] = bottom + (row as f64 + 0.5) * cell_height;
+                y
+            })
+        });
+
+        equilibrium::find(self.derivative, self.x, seeds)
+            .into_iter()
+            .filter(|equilibrium| {
+                let (u, v) = self.project(&equilibrium.y);
+                (left..=right).contains(&u) && (bottom..=top).contains(&v)
+            })
+            .collect()
+    }
+
+    /// Samples the vector field at the center of every cell of the grid
+    /// over `bounds` (left, right, bottom, top). Arrows are normalized
+    /// relative to the cell size, so they appear the same length on the
+    /// chart whatever the aspect ratio of the bounds.
+    pub fn arrows(&self, portrait: &PhasePortrait, bounds: (f64, f64, f64, f64)) -> Vec<Arrow> {
+        let (left, right, bottom, top) = bounds;
+        let cell_width = (right - left) / portrait.columns as f64;
+        let cell_height = (top - bottom) / portrait.rows as f64;
+        let (mut y, mut dy) = self.buffers();
+
+        let mut arrows = vec![];
+        let mut longest: f64 = 0.0;
+        for column in 0..portrait.columns {
+            for row in 0..portrait.rows {
+                let center = (
+                    left + (column as f64 + 0.5) * cell_width,
+                    bottom + (row as f64 + 0.5) * cell_height,
+                );
+                let (du, dv) = self.evaluate(center, &mut y, &mut dy);
+
+                // direction in units of cells, so that both axes are on the
+                // same scale once drawn
+                let (u, v) = (du / cell_width, dv / cell_height);
+                let length = u.hypot(v);
+                if !length.is_finite() || length == 0.0 {
+                    continue;
+                }
+
+                let half = (
+                    0.4 * u / length * cell_width,
+                    0.4 * v / length * cell_height,
+                );
+                let magnitude = du.hypot(dv);
+                longest = longest.max(magnitude);
+                arrows.push(Arrow {
+                    from: (center.0 - half.0, center.1 - half.1),
+                    to: (center.0 + half.0, center.1 + half.1),
+                    magnitude,
+                });
+            }
+        }
+
+        for arrow in &mut arrows {
+            arrow.magnitude /= longest;
+        }
+
+        arrows
+    }
+
+    /// Traces the curves where component `pair.0` (for `first`) or
+    /// `pair.1` of the derivative vanishes, as line segments found by
+    /// marching squares on a `resolution` × `resolution` grid over
+    /// `bounds`.
+    pub fn nullcline(
+        &self,
+        first: bool,
+        resolution: u32,
+        bounds: (f64, f64, f64, f64),
+    ) -> Vec<((f64, f64), (f64, f64))> {
+        let (left, right, bottom, top) = bounds;
+        let n = resolution as usize;
+        let width = (right - left) / n as f64;
+        let height = (top - bottom) / n as f64;
+        let corner = |i: usize, j: usize| (left + i as f64 * width, bottom + j as f64 * height);
+
+        let (mut y, mut dy) = self.buffers();
+        let mut value = |p: (f64, f64)| {
+            let (du, dv) = self.evaluate(p, &mut y, &mut dy);
+            if first {
+                du
+            } else {
+                dv
+            }
+        };
+
+        // values at the corners, row by row
+        let values: Vec<f64> = (0..=n)
+            .flat_map(|j| (0..=n).map(move |i| (i, j)))
+            .map(|(i, j)| value(corner(i, j)))
+            .collect();
+        let at = |i: usize, j: usize| values[j * (n + 1) + i];
+
+        let mut segments = vec![];
+        for j in 0..n {
+            for i in 0..n {
+                // counter-clockwise from the bottom left
+                let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+                let cell = corners.map(|(i, j)| at(i, j));
+                if cell.iter().any(|value| !value.is_finite()) {
+                    continue;
+                }
+
+                // crossings on the bottom, right, top and left edges
+                let crossings: Vec<(f64, f64)> = (0..4)
+                    .filter_map(|edge| {
+                        let (a, b) = (cell[edge], cell[(edge + 1) % 4]);
+                        if (a > 0.0) == (b > 0.0) {
+                            return None;
+                        }
+                        let t = a / (a - b);
+                        let (ia, ja) = corners[edge];
+                        let (ib, jb) = corners[(edge + 1) % 4];
+                        let (pa, pb) = (corner(ia, ja), corner(ib, jb));
+                        Some((pa.0 + t * (pb.0 - pa.0), pa.1 + t * (pb.1 - pa.1)))
+                    })
+                    .collect();
+
+                match crossings[..] {
+                    [a, b] => segments.push((a, b)),
+                    [bottom, right, top, left] => {
+                        // a saddle: the value at the center decides which
+                        // pairs of opposite corners are joined
+                        let center =
+                            value((corner(i, j).0 + width / 2.0, corner(i, j).1 + height / 2.0));
+                        if (center > 0.0) == (cell[0] > 0.0) {
+                            segments.push((bottom, right));
+                            segments.push((top, left));
+                        } else {
+                            segments.push((left, bottom));
+                            segments.push((right, top));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn damped(_: f64, y: &[f64], dy: &mut [f64]) {
+        dy[0] = y[1];
+        dy[1] = -y[0] - y[1];
+    }
+
+    #[test]
+    fn linear_nullclines() {
+        let plane = Plane {
+            derivative: &damped,
+            x: 0.0,
+            pair: (0, 1),
+        };
+        let bounds = (-2.0, 2.0, -1.5, 1.5);
+
+        // y0' = 0 along y1 = 0
+        let segments = plane.nullcline(true, 16, bounds);
+        assert!(!segments.is_empty());
+        for (a, b) in segments {
+            assert!(a.1.abs() < 1e-12 && b.1.abs() < 1e-12);
+        }
+
+        // y1' = 0 along y1 = -y0
+        let segments = plane.nullcline(false, 16, bounds);
+        assert!(!segments.is_empty());
+        for (a, b) in segments {
+            assert!((a.0 + a.1).abs() < 1e-12 && (b.0 + b.1).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn saddle_nullclines() {
+        // y0' = y0 y1 vanishes along both axes, which cross at the center
+        // of a cell
+        let product = |_: f64, y: &[f64], dy: &mut [f64]| {
+            dy[0] = y[0] * y[1];
+            dy[1] = 1.0;
+        };
+        let plane = Plane {
+            derivative: &product,
+            x: 0.0,
+            pair: (0, 1),
+        };
+
+        let segments = plane.nullcline(true, 5, (-1.0, 1.0, -1.0, 1.0));
+        for (a, b) in segments {
+            let on_axis = |p: (f64, f64)| p.0.abs() < 1e-12 || p.1.abs() < 1e-12;
+            assert!(on_axis(a) && on_axis(b));
+        }
+    }
+
+    #[test]
+    fn arrows_follow_the_flow() {
+        let plane = Plane {
+            derivative: &damped,
+            x: 0.0,
+            pair: (0, 1),
+        };
+        let portrait = PhasePortrait {
+            columns: 4,
+            rows: 4,
+            ..PhasePortrait::default()
+        };
+
+        // cells are 1 wide and 2 tall
+        let arrows = plane.arrows(&portrait, (-2.0, 2.0, -4.0, 4.0));
+        assert_eq!(arrows.len(), 16);
+        for arrow in arrows {
+            let center = (
+                (arrow.from.0 + arrow.to.0) / 2.0,
+                (arrow.from.1 + arrow.to.1) / 2.0,
+            );
+            let (du, dv) = (center.1, -center.0 - center.1);
+            let (u, v) = (arrow.to.0 - arrow.from.0, (arrow.to.1 - arrow.from.1) / 2.0);
+
+            assert!((u.hypot(v) - 0.8).abs() < 1e-12);
+            assert!((u * dv / 2.0 - v * du).abs() < 1e-12);
+            assert!(u * du >= 0.0 && v * dv >= 0.0);
+            assert!(arrow.magnitude > 0.0 && arrow.magnitude <= 1.0);
+        }
+    }
+}
@@ -0,0 +1,1051 @@
+use std::path::Path;
+
+use plotters::{
+    coord::Shift,
+    element::{Circle, EmptyElement, PathElement, Polygon, Rectangle, Text},
+    prelude::SVGBackend,
+    prelude::{BitMapBackend, ChartBuilder, DrawingArea, DrawingBackend, IntoDrawingArea},
+    series::LineSeries,
+    style::{Color, HSLColor, RGBColor, BLACK, BLUE, CYAN, GREEN, MAGENTA, RED, WHITE},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    phase::{Arrow, PhasePortrait, Plane},
+    stochastic::Band,
+    Derivative, Point, Stop,
+};
+
+/// Presentation settings for a chart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Style {
+    pub width: u32,
+    pub height: u32,
+    /// Title drawn above the chart.
+    pub caption: Option<String>,
+    pub line_width: u32,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            width: 1280,
+            height: 960,
+            caption: None,
+            line_width: 1,
+        }
+    }
+}
+
+/// Sampling of the direction field of a scalar equation, drawn as short
+/// segments beneath the solution curves.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirectionField {
+    pub columns: u32,
+    pub rows: u32,
+    /// Color each segment by the magnitude of the slope instead of drawing
+    /// them all in grey.
+    pub colored: bool,
+}
+
+impl Default for DirectionField {
+    fn default() -> Self {
+        DirectionField {
+            columns: 30,
+            rows: 20,
+            colored: false,
+        }
+    }
+}
+
+/// One segment of a direction field, centered on a grid point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub from: (f64, f64),
+    pub to: (f64, f64),
+    pub slope: f64,
+}
+
+impl DirectionField {
+    /// Samples `derivative` at the center of every cell of the grid over
+    /// `bounds` (left, right, bottom, top). Segments are normalized relative
+    /// to the cell size, so they appear the same length on the chart
+    /// whatever the aspect ratio of the bounds.
+    pub fn segments(&self, bounds: (f64, f64, f64, f64), derivative: &Derivative) -> Vec<Segment> {
+        let (left, right, bottom, top) = bounds;
+        let cell_width = (right - left) / self.columns as f64;
+        let cell_height = (top - bottom) / self.rows as f64;
+        let mut slope = [0.0];
+
+        let mut segments = Vec::with_capacity((self.columns * self.rows) as usize);
+        for column in 0..self.columns {
+            for row in 0..self.rows {
+                let x = left + (column as f64 + 0.5) * cell_width;
+                let y = bottom + (row as f64 + 0.5) * cell_height;
+                derivative(x, &[y], &mut slope);
+
+                // direction in units of cells, so that both axes are on the
+                // same scale once drawn
+                let (u, v) = if slope[0].is_infinite() {
+                    (0.0, 1.0)
+                } else {
+                    (1.0 / cell_width, slope[0] / cell_height)
+                };
+                let length = u.hypot(v);
+                if !length.is_finite() || length == 0.0 {
+                    continue;
+                }
+
+                let half_x = 0.4 * u / length * cell_width;
+                let half_y = 0.4 * v / length * cell_height;
+                segments.push(Segment {
+                    from: (x - half_x, y - half_y),
+                    to: (x + half_x, y + half_y),
+                    slope: slope[0],
+                });
+            }
+        }
+
+        segments
+    }
+}
+
+/// Blue for flat segments through to red for vertical ones.
+fn slope_color(slope: f64) -> HSLColor {
+    let steepness = slope.abs().atan() / std::f64::consts::FRAC_PI_2;
+    heat_color(steepness)
+}
+
+/// Blue for 0 through to red for 1.
+fn heat_color(fraction: f64) -> HSLColor {
+    HSLColor(2.0 / 3.0 * (1.0 - fraction), 0.8, 0.5)
+}
+
+/// What to draw beneath the trajectories.
+pub enum Overlay<'a> {
+    /// The slopes of a scalar equation, against x.
+    DirectionField(&'a DirectionField, &'a Derivative<'a>),
+    /// The vector field and nullclines of the plane the trajectories are
+    /// projected onto.
+    PhasePortrait(&'a PhasePortrait, Plane<'a>),
+}
+
+/// The two ends of the barbs of `arrow`, a quarter of a cell long.
+fn arrow_head(arrow: &Arrow, cell_width: f64, cell_height: f64) -> [(f64, f64); 2] {
+    // work in units of cells, where the arrow has its drawn proportions
+    let u = (arrow.to.0 - arrow.from.0) / cell_width;
+    let v = (arrow.to.1 - arrow.from.1) / cell_height;
+    let length = u.hypot(v);
+    let (u, v) = (u / length * 0.25, v / length * 0.25);
+    let (sin, cos) = (5.0 * std::f64::consts::PI / 6.0).sin_cos();
+
+    [sin, -sin].map(|sin| {
+        (
+            arrow.to.0 + (u * cos - v * sin) * cell_width,
+            arrow.to.1 + (u * sin + v * cos) * cell_height,
+        )
+    })
+}
+
+const FIELD_GREY: RGBColor = RGBColor(160, 160, 160);
+
+/// How a chart is rendered to its output file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// A raster image, encoded according to the file extension (PNG, BMP or
+    /// JPEG).
+    Bitmap,
+    /// A scalable vector graphic, for reports and print.
+    Svg,
+}
+
+impl Backend {
+    pub const NAMES: &'static [&'static str] = &["bitmap", "svg"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bitmap" => Some(Backend::Bitmap),
+            "svg" => Some(Backend::Svg),
+            _ => None,
+        }
+    }
+
+    /// The backend suited to the extension of `path`, falling back to a
+    /// bitmap.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("svg") => Backend::Svg,
+            _ => Backend::Bitmap,
+        }
+    }
+}
+
+/// Which two quantities of a [`Point`] to draw against each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    /// A single component of `y` against `x`.
+    Component(usize),
+    /// Two components of `y` against each other, as in a phase plane.
+    Pair(usize, usize),
+}
+
+impl Projection {
+    pub fn apply(self, point: &Point) -> (f64, f64) {
+        match self {
+            Projection::Component(i) => (point.x, point.y[i]),
+            Projection::Pair(i, j) => (point.y[i], point.y[j]),
+        }
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            Projection::Component(i) => format!("y{i}"),
+            Projection::Pair(i, j) => format!("(y{i}, y{j})"),
+        }
+    }
+}
+
+pub fn decide_bounds(x_range: (f64, f64), y_range: (f64, f64)) -> (f64, f64, f64, f64) {
+    (
+        x_range.0 - 1.0,
+        x_range.1 + 1.0,
+        y_range.0 - 1.0,
+        y_range.1 + 1.0,
+    )
+}
+
+fn range(values: impl Iterator<Item = f64> + Clone) -> (f64, f64) {
+    (
+        values.clone().reduce(f64::min).unwrap_or(0.0),
+        values.reduce(f64::max).unwrap_or(0.0),
+    )
+}
+
+/// Draws every projection of every dataset onto one chart saved at `path`,
+/// over `overlay` when given. The legend notes the datasets whose entry in
+/// `stops` says they blew up; `stops` may be empty when unknown.
+pub fn draw_datasets(
+    path: impl AsRef<Path>,
+    backend: Backend,
+    style: &Style,
+    datasets: &[Vec<Point>],
+    stops: &[Stop],
+    projections: &[Projection],
+    overlay: Option<Overlay>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let size = (style.width, style.height);
+
+    match backend {
+        Backend::Bitmap => draw(
+            BitMapBackend::new(path.as_ref(), size).into_drawing_area(),
+            style,
+            datasets,
+            stops,
+            projections,
+            overlay,
+        ),
+        Backend::Svg => draw(
+            SVGBackend::new(path.as_ref(), size).into_drawing_area(),
+            style,
+            datasets,
+            stops,
+            projections,
+            overlay,
+        ),
+    }
+}
+
+fn draw<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    style: &Style,
+    datasets: &[Vec<Point>],
+    stops: &[Stop],
+    projections: &[Projection],
+    overlay: Option<Overlay>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let projected = || {
+        projections.iter().flat_map(move |&projection| {
+            datasets
+                .iter()
+                .flatten()
+                .map(move |point| projection.apply(point))
+        })
+    };
+
+    let (left_bound, right_bound, bottom_bound, top_bound) = decide_bounds(
+        range(projected().map(|a| a.0)),
+        range(projected().map(|a| a.1)),
+    );
+
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root);
+    if let Some(caption) = &style.caption {
+        chart.caption(caption, ("sans-serif", 30));
+    }
+
+    let mut chart = chart
+        .margin(5)
+        .x_label_area_size(30)
+        .y_label_area_size(30)
+        .build_cartesian_2d(left_bound..right_bound, bottom_bound..top_bound)?;
+
+    chart.configure_mesh().draw()?;
+
+    let bounds = (left_bound, right_bound, bottom_bound, top_bound);
+    match overlay {
+        Some(Overlay::DirectionField(field, derivative)) => {
+            for segment in field.segments(bounds, derivative) {
+                let color = if field.colored {
+                    slope_color(segment.slope).to_rgba()
+                } else {
+                    FIELD_GREY.to_rgba()
+                };
+                chart.draw_series(LineSeries::new([segment.from, segment.to], color))?;
+            }
+        }
+        Some(Overlay::PhasePortrait(portrait, plane)) => {
+            let (cell_width, cell_height) = (
+                (right_bound - left_bound) / portrait.columns as f64,
+                (top_bound - bottom_bound) / portrait.rows as f64,
+            );
+            for arrow in plane.arrows(portrait, bounds) {
+                let color = if portrait.colored {
+                    heat_color(arrow.magnitude).to_rgba()
+                } else {
+                    FIELD_GREY.to_rgba()
+                };
+                let [left, right] = arrow_head(&arrow, cell_width, cell_height);
+                chart.draw_series([
+                    PathElement::new(vec![arrow.from, arrow.to], color),
+                    PathElement::new(vec![left, arrow.to, right], color),
+                ])?;
+            }
+
+            if portrait.nullclines {
+                let (i, j) = plane.pair;
+                for (first, label, color) in [(true, i, MAGENTA), (false, j, CYAN)] {
+                    chart
+                        .draw_series(
+                            plane
+                                .nullcline(first, portrait.resolution, bounds)
+                                .into_iter()
+                                .map(|(a, b)| PathElement::new(vec![a, b], color.stroke_width(2))),
+                        )?
+                        .label(format!("y{label}' = 0"))
+                        .legend(move |(x, y)| {
+                            PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2))
+                        });
+                }
+            }
+
+            if portrait.equilibria {
+                chart.draw_series(plane.equilibria(portrait, bounds).into_iter().map(
+                    |equilibrium| {
+                        // filled when stable, hollow otherwise
+                        let marker = if equilibrium.classification.is_stable() {
+                            BLACK.filled()
+                        } else {
+                            BLACK.stroke_width(2)
+                        };
+                        EmptyElement::at(plane.project(&equilibrium.y))
+                            + Circle::new((0, 0), 5, marker)
+                            + Text::new(
+                                equilibrium.classification.to_string(),
+                                (8, -18),
+                                ("sans-serif", 15),
+                            )
+                    },
+                ))?;
+            }
+        }
+        None => {}
+    }
+
+    let colors = [&RED, &BLACK, &BLUE, &GREEN];
+
+    for (i, points) in datasets.iter().enumerate() {
+        let blow_up = match stops.get(i) {
+            Some(stop @ Stop::Degenerate { .. }) => format!(", {stop}"),
+            _ => String::new(),
+        };
+        for (j, &projection) in projections.iter().enumerate() {
+            chart
+                .draw_series(LineSeries::new(
+                    points.iter().map(|point| projection.apply(point)),
+                    colors[(i * projections.len() + j) % colors.len()]
+                        .stroke_width(style.line_width),
+                ))?
+                .label(format!("{}{blow_up}", projection.label()));
+        }
+    }
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+
+    Ok(())
+}
+
+/// The change in `quantity` along each dataset from its value at the first
+/// point, against x.
+pub fn drift(
+    datasets: &[Vec<Point>],
+    quantity: impl Fn(f64, &[f64]) -> f64,
+) -> Vec<Vec<(f64, f64)>> {
+    datasets
+        .iter()
+        .map(|points| {
+            let initial = points
+                .first()
+                .map_or(0.0, |point| quantity(point.x, &point.y));
+            points
+                .iter()
+                .map(|point| (point.x, quantity(point.x, &point.y) - initial))
+                .collect()
+        })
+        .collect()
+}
+
+/// Draws the [`drift`] of a quantity that should be conserved, named by
+/// `label`, onto a chart saved at `path`. Unlike [`draw_datasets`] the
+/// vertical axis is fitted to the drift however small it is.
+pub fn draw_drift(
+    path: impl AsRef<Path>,
+    backend: Backend,
+    style: &Style,
+    drifts: &[Vec<(f64, f64)>],
+    stops: &[Stop],
+    label: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let size = (style.width, style.height);
+
+    match backend {
+        Backend::Bitmap => draw_drift_on(
+            BitMapBackend::new(path.as_ref(), size).into_drawing_area(),
+            style,
+            drifts,
+            stops,
+            label,
+        ),
+        Backend::Svg => draw_drift_on(
+            SVGBackend::new(path.as_ref(), size).into_drawing_area(),
+            style,
+            drifts,
+            stops,
+            label,
+        ),
+    }
+}
+
+fn draw_drift_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    style: &Style,
+    drifts: &[Vec<(f64, f64)>],
+    stops: &[Stop],
+    label: &str,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let points = || drifts.iter().flatten();
+    let (left, right) = range(points().map(|a| a.0));
+    let (bottom, top) = range(points().map(|a| a.1));
+    let padding = if top > bottom {
+        (top - bottom) / 20.0
+    } else {
+        1.0
+    };
+
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root);
+    if let Some(caption) = &style.caption {
+        chart.caption(caption, ("sans-serif", 30));
+    }
+
+    let mut chart = chart
+        .margin(5)
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(left..right, bottom - padding..top + padding)?;
+
+    chart.configure_mesh().draw()?;
+
+    let colors = [&RED, &BLACK, &BLUE, &GREEN];
+    for (i, drift) in drifts.iter().enumerate() {
+        let blow_up = match stops.get(i) {
+            Some(stop @ Stop::Degenerate { .. }) => format!(", {stop}"),
+            _ => String::new(),
+        };
+        chart
+            .draw_series(LineSeries::new(
+                drift.iter().copied(),
+                colors[i % colors.len()].stroke_width(style.line_width),
+            ))?
+            .label(format!("change in {label}{blow_up}"));
+    }
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+
+    Ok(())
+}
+
+/// Draws the mean of every component of each ensemble within a band
+/// between the `percentiles` it was computed with, onto a chart saved at
+/// `path`.
+pub fn draw_bands(
+    path: impl AsRef<Path>,
+    backend: Backend,
+    style: &Style,
+    ensembles: &[Vec<Band>],
+    dimension: usize,
+    percentiles: (f64, f64),
+) -> Result<(), Box<dyn std::error::Error>> {
+    let size = (style.width, style.height);
+
+    match backend {
+        Backend::Bitmap => draw_bands_on(
+            BitMapBackend::new(path.as_ref(), size).into_drawing_area(),
+            style,
+            ensembles,
+            dimension,
+            percentiles,
+        ),
+        Backend::Svg => draw_bands_on(
+            SVGBackend::new(path.as_ref(), size).into_drawing_area(),
+            style,
+            ensembles,
+            dimension,
+            percentiles,
+        ),
+    }
+}
+
+fn draw_bands_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    style: &Style,
+    ensembles: &[Vec<Band>],
+    dimension: usize,
+    (lower, upper): (f64, f64),
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let bands = || ensembles.iter().flatten();
+    let values = || bands().flat_map(|band| band.lower.iter().chain(&band.upper).copied());
+
+    let (left_bound, right_bound, bottom_bound, top_bound) =
+        decide_bounds(range(bands().map(|band| band.x)), range(values()));
+
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root);
+    if let Some(caption) = &style.caption {
+        chart.caption(caption, ("sans-serif", 30));
+    }
+
+    let mut chart = chart
+        .margin(5)
+        .x_label_area_size(30)
+        .y_label_area_size(30)
+        .build_cartesian_2d(left_bound..right_bound, bottom_bound..top_bound)?;
+
+    chart.configure_mesh().draw()?;
+
+    let colors = [&RED, &BLACK, &BLUE, &GREEN];
+    for (i, ensemble) in ensembles.iter().enumerate() {
+        for component in 0..dimension {
+            let color = colors[(i * dimension + component) % colors.len()];
+            // along the upper percentile and back along the lower
+            let outline: Vec<(f64, f64)> = ensemble
+                .iter()
+                .map(|band| (band.x, band.upper[component]))
+                .chain(
+                    ensemble
+                        .iter()
+                        .rev()
+                        .map(|band| (band.x, band.lower[component])),
+                )
+                .collect();
+            chart
+                .draw_series([Polygon::new(outline, color.mix(0.2).filled())])?
+                .label(format!("y{component}, {lower}th to {upper}th percentile"))
+                .legend(move |(x, y)| {
+                    Rectangle::new([(x, y - 5), (x + 20, y + 5)], color.mix(0.2).filled())
+                });
+            chart
+                .draw_series(LineSeries::new(
+                    ensemble.iter().map(|band| (band.x, band.mean[component])),
+                    color.stroke_width(style.line_width),
+                ))?
+                .label(format!("mean of y{component}"))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+    }
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+
+    Ok(())
+}
+
+/// The most columns of a heatmap; longer solutions are thinned out.
+const HEATMAP_COLUMNS: usize = 400;
+
+/// Evenly spaced `points`, at most `count` of them, followed by the last
+/// when it is not among them.
+fn thin(points: &[Point], count: usize) -> Vec<&Point> {
+    let stride = points.len().div_ceil(count.max(1)).max(1);
+    let mut thinned: Vec<&Point> = points.iter().step_by(stride).collect();
+    if let Some(last) = points.last() {
+        if !std::ptr::eq(thinned[thinned.len() - 1], last) {
+            thinned.push(last);
+        }
+    }
+    thinned
+}
+
+/// Draws the solution of a [`crate::pde`] problem as a heatmap of `u`, with
+/// time across and the grid `positions` up, onto a chart saved at `path`.
+pub fn draw_heatmap(
+    path: impl AsRef<Path>,
+    backend: Backend,
+    style: &Style,
+    positions: &[f64],
+    points: &[Point],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let size = (style.width, style.height);
+
+    match backend {
+        Backend::Bitmap => draw_heatmap_on(
+            BitMapBackend::new(path.as_ref(), size).into_drawing_area(),
+            style,
+            positions,
+            points,
+        ),
+        Backend::Svg => draw_heatmap_on(
+            SVGBackend::new(path.as_ref(), size).into_drawing_area(),
+            style,
+            positions,
+            points,
+        ),
+    }
+}
+
+fn draw_heatmap_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    style: &Style,
+    positions: &[f64],
+    points: &[Point],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let columns = thin(points, HEATMAP_COLUMNS);
+    let (start, end) = range(points.iter().map(|point| point.x));
+    let (low, high) = range(points.iter().flat_map(|point| point.y.iter().copied()));
+    // each position is the middle of its row, which the ends cut in half
+    let last = positions.len() - 1;
+    let edges: Vec<f64> = (0..=positions.len())
+        .map(|i| match i {
+            0 => positions[0],
+            i if i > last => positions[last],
+            i => (positions[i - 1] + positions[i]) / 2.0,
+        })
+        .collect();
+
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root);
+    if let Some(caption) = &style.caption {
+        chart.caption(caption, ("sans-serif", 30));
+    }
+
+    let mut chart = chart
+        .margin(5)
+        .x_label_area_size(30)
+        .y_label_area_size(30)
+        .build_cartesian_2d(start..end, positions[0]..positions[last])?;
+
+    chart.configure_mesh().disable_mesh().draw()?;
+
+    let color = |u: f64| {
+        let fraction = if high > low {
+            (u - low) / (high - low)
+        } else {
+            0.5
+        };
+        heat_color(fraction).filled()
+    };
+    // each column holds from its time to the next
+    let (edges, color) = (&edges, &color);
+    chart.draw_series(columns.windows(2).flat_map(|pair| {
+        let (now, next) = (pair[0], pair[1].x);
+        now.y
+            .iter()
+            .enumerate()
+            .map(move |(i, &u)| Rectangle::new([(now.x, edges[i]), (next, edges[i + 1])], color(u)))
+    }))?;
+
+    for (value, fraction) in [(low, 0.0), (high, 1.0)] {
+        let swatch = heat_color(fraction).filled();
+        chart
+            .draw_series(std::iter::empty::<Rectangle<(f64, f64)>>())?
+            // four decimals tell the ends of the scale apart
+            .label(format!("u = {}", (value * 1e4).round() / 1e4))
+            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], swatch));
+    }
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+
+    Ok(())
+}
+
+/// Draws the solution of a [`crate::pde`] problem as an animated GIF saved
+/// at `path`, a frame of `u` along the grid `positions` for each of up to
+/// `frames` times, `delay` milliseconds apart. The vertical axis spans the
+/// whole solution, so that frames compare.
+pub fn draw_animation(
+    path: impl AsRef<Path>,
+    style: &Style,
+    positions: &[f64],
+    points: &[Point],
+    frames: usize,
+    delay: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let root =
+        BitMapBackend::gif(path.as_ref(), (style.width, style.height), delay)?.into_drawing_area();
+    let (left, right) = range(positions.iter().copied());
+    let (bottom, top) = range(points.iter().flat_map(|point| point.y.iter().copied()));
+    let padding = if top > bottom {
+        (top - bottom) / 20.0
+    } else {
+        1.0
+    };
+
+    for point in thin(points, frames) {
+        root.fill(&WHITE)?;
+        let caption = match &style.caption {
+            Some(caption) => format!("{caption}, t = {}", point.x),
+            None => format!("t = {}", point.x),
+        };
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(caption, ("sans-serif", 30))
+            .margin(5)
+            .x_label_area_size(30)
+            .y_label_area_size(60)
+            .build_cartesian_2d(left..right, bottom - padding..top + padding)?;
+
+        chart.configure_mesh().draw()?;
+        chart.draw_series(LineSeries::new(
+            positions.iter().copied().zip(point.y.iter().copied()),
+            RED.stroke_width(style.line_width),
+        ))?;
+
+        root.present()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, fs, path::PathBuf};
+
+    /// Compares `actual` with the checked-in file `tests/golden/{name}`, or
+    /// rewrites the file when `UPDATE_GOLDEN` is set.
+    fn assert_golden(name: &str, actual: &str) {
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+            .iter()
+            .collect();
+
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("{}: {e}; rerun with UPDATE_GOLDEN=1", path.display()));
+        assert!(
+            expected == actual,
+            "{} differs from the rendered chart; rerun with UPDATE_GOLDEN=1 if the change is intended",
+            path.display()
+        );
+    }
+
+    fn render_svg(
+        style: &Style,
+        datasets: &[Vec<Point>],
+        stops: &[Stop],
+        projections: &[Projection],
+        overlay: Option<Overlay>,
+    ) -> String {
+        let mut svg = String::new();
+        draw(
+            SVGBackend::with_string(&mut svg, (style.width, style.height)).into_drawing_area(),
+            style,
+            datasets,
+            stops,
+            projections,
+            overlay,
+        )
+        .unwrap();
+        svg
+    }
+
+    fn circle() -> Vec<Point> {
+        (0..=16)
+            .map(|i| {
+                let t = i as f64 * std::f64::consts::TAU / 16.0;
+                (t, [t.cos(), -t.sin()]).into()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn backend_from_path() {
+        assert_eq!(Backend::from_path("chart.svg".as_ref()), Backend::Svg);
+        assert_eq!(Backend::from_path("chart.SVG".as_ref()), Backend::Svg);
+        assert_eq!(Backend::from_path("chart.png".as_ref()), Backend::Bitmap);
+        assert_eq!(Backend::from_path("chart".as_ref()), Backend::Bitmap);
+    }
+
+    #[test]
+    fn direction_field_segments() {
+        let field = DirectionField {
+            columns: 4,
+            rows: 2,
+            colored: false,
+        };
+        // cells are 1 wide and 10 tall
+        let derivative = |x: f64, _: &[f64], dy: &mut [f64]| dy[0] = 10.0 * x;
+        let segments = field.segments((-2.0, 2.0, 0.0, 20.0), &derivative);
+        assert_eq!(segments.len(), 8);
+
+        for segment in segments {
+            let (x, y) = (
+                (segment.from.0 + segment.to.0) / 2.0,
+                (segment.from.1 + segment.to.1) / 2.0,
+            );
+            assert!(x.fract().abs() == 0.5 && y % 10.0 == 5.0);
+            assert_eq!(segment.slope, 10.0 * x);
+
+            // the same length and direction in units of cells
+            let dx = segment.to.0 - segment.from.0;
+            let dy = (segment.to.1 - segment.from.1) / 10.0;
+            assert!((dx.hypot(dy) - 0.8).abs() < 1e-12);
+            assert!((dy / dx - x).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn svg_components() {
+        let style = Style {
+            width: 320,
+            height: 240,
+            ..Style::default()
+        };
+        let svg = render_svg(
+            &style,
+            &[circle()],
+            &[],
+            &[Projection::Component(0), Projection::Component(1)],
+            None,
+        );
+        assert_golden("components.svg", &svg);
+    }
+
+    #[test]
+    fn legend_notes_blow_ups() {
+        let style = Style {
+            width: 320,
+            height: 240,
+            ..Style::default()
+        };
+        let stops = [
+            Stop::Condition("x reached 6.3".to_string()),
+            Stop::Degenerate { x: 3.5 },
+        ];
+        let svg = render_svg(
+            &style,
+            &[circle(), circle()[..8].to_vec()],
+            &stops,
+            &[Projection::Component(0)],
+            None,
+        );
+
+        assert_eq!(svg.matches("blow-up").count(), 1);
+        assert!(svg.contains("y0, blow-up at x = 3.5"));
+    }
+
+    #[test]
+    fn svg_drift() {
+        let style = Style {
+            width: 320,
+            height: 240,
+            ..Style::default()
+        };
+        // a slightly wobbling circle, its radius off by at most 1e-3
+        let wobbly: Vec<Point> = circle()
+            .into_iter()
+            .map(|point| {
+                let r = 1.0 + 1e-3 * (3.0 * point.x).sin();
+                (point.x, [r * point.y[0], r * point.y[1]]).into()
+            })
+            .collect();
+        let drifts = drift(&[wobbly], |_, y| y[0].hypot(y[1]));
+        assert_eq!(drifts[0][0], (0.0, 0.0));
+        assert!(drifts[0].iter().all(|&(_, d)| d.abs() <= 1e-3 + 1e-12));
+
+        let mut svg = String::new();
+        draw_drift_on(
+            SVGBackend::with_string(&mut svg, (style.width, style.height)).into_drawing_area(),
+            &style,
+            &drifts,
+            &[],
+            "r",
+        )
+        .unwrap();
+        assert_golden("drift.svg", &svg);
+    }
+
+    #[test]
+    fn svg_bands() {
+        let style = Style {
+            width: 320,
+            height: 240,
+            ..Style::default()
+        };
+        let ensemble: Vec<Band> = circle()
+            .into_iter()
+            .map(|point| Band {
+                x: point.x,
+                mean: point.y.clone(),
+                lower: point.y.iter().map(|y| y - 0.1 * point.x).collect(),
+                upper: point.y.iter().map(|y| y + 0.1 * point.x).collect(),
+            })
+            .collect();
+
+        let mut svg = String::new();
+        draw_bands_on(
+            SVGBackend::with_string(&mut svg, (style.width, style.height)).into_drawing_area(),
+            &style,
+            &[ensemble],
+            2,
+            (5.0, 95.0),
+        )
+        .unwrap();
+        assert!(svg.contains("y1, 5th to 95th percentile"));
+        assert_golden("bands.svg", &svg);
+    }
+
+    #[test]
+    fn svg_heatmap() {
+        let style = Style {
+            width: 320,
+            height: 240,
+            ..Style::default()
+        };
+        let positions = [0.0, 0.25, 0.5, 0.75, 1.0];
+        let points: Vec<Point> = (0..4)
+            .map(|k| {
+                let t = k as f64 * 0.1;
+                let u = positions.map(|x| (-t).exp() * (std::f64::consts::PI * x).sin());
+                (t, u).into()
+            })
+            .collect();
+
+        let mut svg = String::new();
+        draw_heatmap_on(
+            SVGBackend::with_string(&mut svg, (style.width, style.height)).into_drawing_area(),
+            &style,
+            &positions,
+            &points,
+        )
+        .unwrap();
+        assert!(svg.contains("u = 1"));
+        assert_golden("heatmap.svg", &svg);
+    }
+
+    #[test]
+    fn thinning_keeps_the_ends() {
+        let points: Vec<Point> = (0..10).map(|i| (i as f64, 0.0).into()).collect();
+        let x = |count| -> Vec<f64> { thin(&points, count).iter().map(|p| p.x).collect() };
+        assert_eq!(x(4), [0.0, 3.0, 6.0, 9.0]);
+        assert_eq!(x(3), [0.0, 4.0, 8.0, 9.0]);
+        assert_eq!(x(20).len(), 10);
+    }
+
+    #[test]
+    fn svg_phase_plane() {
+        let style = Style {
+            width: 320,
+            height: 320,
+            caption: Some("Phase plane".to_string()),
+            line_width: 2,
+        };
+        let svg = render_svg(&style, &[circle()], &[], &[Projection::Pair(0, 1)], None);
+        assert_golden("phase_plane.svg", &svg);
+    }
+
+    #[test]
+    fn svg_phase_portrait() {
+        let style = Style {
+            width: 320,
+            height: 320,
+            ..Style::default()
+        };
+        let portrait = PhasePortrait {
+            columns: 8,
+            rows: 8,
+            resolution: 20,
+            ..PhasePortrait::default()
+        };
+        let pendulum = |_: f64, y: &[f64], dy: &mut [f64]| {
+            dy[0] = y[1];
+            dy[1] = -y[0].sin();
+        };
+        let plane = Plane {
+            derivative: &pendulum,
+            x: 0.0,
+            pair: (0, 1),
+        };
+
+        let svg = render_svg(
+            &style,
+            &[circle()],
+            &[],
+            &[Projection::Pair(0, 1)],
+            Some(Overlay::PhasePortrait(&portrait, plane)),
+        );
+        assert_golden("phase_portrait.svg", &svg);
+    }
+}
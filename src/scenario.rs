@@ -0,0 +1,1282 @@
+//! Declarative descriptions of a batch of runs, loaded from TOML or JSON so
+//! that a run can be reproduced from a single checked-in file.
+//!
+//! ```toml
+//! equations = ["y1", "-y0"]
+//! method = "rk45"
+//! step = 0.01
+//!
+//! [tolerance]
+//! absolute = 1e-8
+//! relative = 1e-8
+//!
+//! [initial.grid]
+//! start_x = 0.0
+//! axes = [{ from = -2.0, to = 2.0, count = 5 }, { from = 0.0, to = 0.0, count = 1 }]
+//!
+//! [end]
+//! max_x = 20.0
+//! max_steps = 100000
+//!
+//! [[events]]
+//! expression = "y0"
+//! direction = "falling"
+//!
+//! [plot]
+//! output = "oscillator.png"
+//! pairs = [[0, 1]]
+//!
+//! [plot.style]
+//! caption = "Harmonic oscillator"
+//! ```
+
+use std::{fmt, fs, path::Path, path::PathBuf, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event::{Action, Direction, Event},
+    expr::{Expr, ExprSystem},
+    phase::PhasePortrait,
+    plot::{Backend, DirectionField, Projection, Style},
+    stochastic::DiffusionFunction,
+    termination::{All, Any, MaxSteps, SteadyState, Termination, WallClock, XRange, YBox},
+    JacobianFunction, Method, Point, Tolerance,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    /// Right-hand side of each component of the system, see [`crate::expr`].
+    pub equations: Vec<String>,
+    /// One of [`Method::NAMES`].
+    #[serde(default = "default_method")]
+    pub method: String,
+    /// Step size, or the initial step size for adaptive methods. A negative
+    /// step integrates towards decreasing x.
+    #[serde(default = "default_step")]
+    pub step: f64,
+    /// Integrate both ways from the initial point and join the branches, in
+    /// which case the sign of `step` is ignored.
+    #[serde(default)]
+    pub bidirectional: bool,
+    #[serde(default)]
+    pub tolerance: Tolerance,
+    /// The Jacobian of the system for implicit methods, one row of
+    /// expressions per equation, `jacobian[i][j]` being the derivative of
+    /// equation `i` with respect to `y{j}`. Estimated numerically when left
+    /// out.
+    #[serde(default)]
+    pub jacobian: Option<Vec<Vec<String>>>,
+    pub initial: InitialConditions,
+    #[serde(default)]
+    pub end: Limits,
+    /// Zeros of functions of the state to record or stop at.
+    #[serde(default)]
+    pub events: Vec<EventSettings>,
+    /// Replace the points of every trajectory with its dense output at these
+    /// values of x, leaving out those it does not reach.
+    #[serde(default)]
+    pub resample: Option<Axis>,
+    /// Noise driving the equations, for the stochastic methods.
+    #[serde(default)]
+    pub noise: Option<NoiseSettings>,
+    /// Constant delays the equations may look back by, making them a delay
+    /// differential equation.
+    #[serde(default)]
+    pub delay: Option<DelaySettings>,
+    #[serde(default)]
+    pub plot: PlotSettings,
+}
+
+fn default_method() -> String {
+    "euler".to_string()
+}
+
+fn default_step() -> f64 {
+    0.001
+}
+
+/// The noise of a stochastic equation `dY = f(x, Y) dx + g(x, Y) dW`,
+/// whose drift `f` is given by the equations.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NoiseSettings {
+    /// The diffusion `g` of each component, scaling its own Wiener process.
+    pub diffusion: Vec<String>,
+    /// Seeds the noise of every trajectory, so that a run can be repeated.
+    #[serde(default)]
+    pub seed: u64,
+    /// Sample paths to take from each initial point.
+    #[serde(default = "default_paths")]
+    pub paths: usize,
+}
+
+fn default_paths() -> usize {
+    1
+}
+
+/// The delays of a delay differential equation, whose equations refer to
+/// component `i` at `x` minus delay `j` as `y{i}_{j}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DelaySettings {
+    pub delays: Vec<f64>,
+    /// The state before the start, one expression in `x` per component.
+    /// Each trajectory holds its initial value when left out.
+    #[serde(default)]
+    pub history: Option<Vec<String>>,
+}
+
+/// The starting points of every trajectory in a scenario.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum InitialConditions {
+    /// `count` trajectories starting at `start_y`, with `y0` offset by
+    /// `y_spread` for each further trajectory.
+    Spread {
+        start_x: f64,
+        start_y: Vec<f64>,
+        y_spread: f64,
+        count: usize,
+    },
+    /// One trajectory per listed initial value of `y`.
+    List { start_x: f64, y: Vec<Vec<f64>> },
+    /// One trajectory per point of the grid spanned by one axis per
+    /// component.
+    Grid { start_x: f64, axes: Vec<Axis> },
+}
+
+/// `count` evenly spaced values from `from` to `to` inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Axis {
+    pub from: f64,
+    pub to: f64,
+    pub count: usize,
+}
+
+impl Axis {
+    pub fn values(self) -> impl Iterator<Item = f64> {
+        (0..self.count).map(move |i| {
+            if self.count == 1 {
+                self.from
+            } else {
+                self.from + (self.to - self.from) * i as f64 / (self.count - 1) as f64
+            }
+        })
+    }
+
+    /// Every point of the grid spanned by one axis per component, varying
+    /// the last component fastest.
+    pub fn grid(axes: &[Axis]) -> Vec<Vec<f64>> {
+        axes.iter().fold(vec![vec![]], |points, axis| {
+            points
+                .iter()
+                .flat_map(|point| {
+                    axis.values().map(move |value| {
+                        let mut point = point.clone();
+                        point.push(value);
+                        point
+                    })
+                })
+                .collect()
+        })
+    }
+}
+
+/// An event function given as an expression in x and y, see
+/// [`crate::event`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventSettings {
+    pub expression: String,
+    #[serde(default)]
+    pub direction: Direction,
+    #[serde(default)]
+    pub action: Action,
+}
+
+/// How far past the initial x a trajectory runs, in each direction it is
+/// integrated, when no limit on x is given.
+const DEFAULT_X_SPAN: f64 = 150.0;
+
+/// The limits turned into a [`Termination`] for every trajectory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Defaults to 150 past the initial x when integrating forwards, and
+    /// `min_x` to 150 before it when integrating backwards; see
+    /// [`Scenario::x_range`].
+    pub max_x: Option<f64>,
+    pub min_x: Option<f64>,
+    pub max_abs_y: Option<f64>,
+    /// `[lower, upper]` bounds of each component of `y`, in order.
+    pub y_box: Option<Vec<[f64; 2]>>,
+    pub max_steps: Option<usize>,
+    /// Wall-clock budget of each trajectory, in seconds.
+    pub time_limit: Option<f64>,
+    pub steady_state: Option<SteadyStateSettings>,
+    /// Whether a trajectory ends once any of the limits is reached, or only
+    /// once all of them are at the same time.
+    pub combine: Combine,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_x: None,
+            min_x: None,
+            max_abs_y: Some(150.0),
+            y_box: None,
+            max_steps: None,
+            time_limit: None,
+            steady_state: None,
+            combine: Combine::Any,
+        }
+    }
+}
+
+/// Ends a trajectory once every component of `y'` has stayed below
+/// `epsilon` in magnitude for `steps` consecutive steps.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SteadyStateSettings {
+    pub epsilon: f64,
+    #[serde(default = "default_steady_steps")]
+    pub steps: usize,
+}
+
+fn default_steady_steps() -> usize {
+    10
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Combine {
+    #[default]
+    Any,
+    All,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlotSettings {
+    pub output: PathBuf,
+    /// Pairs of components to draw against each other. When empty, every
+    /// component is drawn against x.
+    pub pairs: Vec<(usize, usize)>,
+    /// Rendering backend, chosen from the extension of `output` when unset.
+    pub backend: Option<Backend>,
+    /// Direction field to draw beneath the curves of a scalar equation.
+    pub direction_field: Option<DirectionField>,
+    /// Vector field and nullclines to draw beneath the trajectories of a
+    /// two-component system plotted in its phase plane. The field is
+    /// evaluated at the initial x, so it only describes autonomous systems
+    /// exactly.
+    pub phase_portrait: Option<PhasePortrait>,
+    /// A quantity that should stay constant along the trajectories, like
+    /// the energy of a Hamiltonian system, as an expression in x and y.
+    /// When set, the chart shows how far it strays from its initial value
+    /// over x instead of the trajectories.
+    pub conserved: Option<String>,
+    /// Instead of every sample path of a stochastic equation, draw the mean
+    /// of the paths from each initial point within a band between two
+    /// percentiles.
+    pub ensemble: Option<EnsembleBands>,
+    pub style: Style,
+}
+
+/// The percentiles bounding the band around the mean of an ensemble.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnsembleBands {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Default for EnsembleBands {
+    fn default() -> Self {
+        EnsembleBands {
+            lower: 5.0,
+            upper: 95.0,
+        }
+    }
+}
+
+impl Default for PlotSettings {
+    fn default() -> Self {
+        PlotSettings {
+            output: "output.png".into(),
+            pairs: vec![],
+            backend: None,
+            direction_field: None,
+            phase_portrait: None,
+            conserved: None,
+            ensemble: None,
+            style: Style::default(),
+        }
+    }
+}
+
+impl PlotSettings {
+    /// Checks the settings against a system of `dimension` components,
+    /// reporting the first field that is out of range.
+    pub fn validate(&self, dimension: usize) -> Result<(), ScenarioError> {
+        for (i, &(a, b)) in self.pairs.iter().enumerate() {
+            if a.max(b) >= dimension {
+                return Err(ScenarioError::invalid(
+                    format!("plot.pairs[{i}]"),
+                    format!("the system only has {dimension} component(s)"),
+                ));
+            }
+        }
+
+        if self.style.width == 0 || self.style.height == 0 {
+            return Err(ScenarioError::invalid(
+                "plot.style",
+                "`width` and `height` must be positive",
+            ));
+        }
+
+        if let Some(field) = &self.direction_field {
+            if dimension != 1 || !self.pairs.is_empty() {
+                return Err(ScenarioError::invalid(
+                    "plot.direction_field",
+                    "only a scalar equation plotted against x has a direction field",
+                ));
+            }
+            if field.columns == 0 || field.rows == 0 {
+                return Err(ScenarioError::invalid(
+                    "plot.direction_field",
+                    "`columns` and `rows` must be positive",
+                ));
+            }
+        }
+
+        if let Some(portrait) = &self.phase_portrait {
+            if dimension != 2 || self.pairs.len() != 1 || self.pairs[0].0 == self.pairs[0].1 {
+                return Err(ScenarioError::invalid(
+                    "plot.phase_portrait",
+                    "only a two-component system plotted as one pair has a phase portrait",
+                ));
+            }
+            if portrait.columns == 0 || portrait.rows == 0 || portrait.resolution == 0 {
+                return Err(ScenarioError::invalid(
+                    "plot.phase_portrait",
+                    "`columns`, `rows` and `resolution` must be positive",
+                ));
+            }
+        }
+
+        if self.conserved.is_some()
+            && (!self.pairs.is_empty()
+                || self.direction_field.is_some()
+                || self.phase_portrait.is_some())
+        {
+            return Err(ScenarioError::invalid(
+                "plot.conserved",
+                "draws its own chart, without pairs, a direction field or a phase portrait",
+            ));
+        }
+        self.conserved(dimension)?;
+
+        if let Some(bands) = self.ensemble {
+            if !(0.0 <= bands.lower && bands.lower < bands.upper && bands.upper <= 100.0) {
+                return Err(ScenarioError::invalid(
+                    "plot.ensemble",
+                    "the percentiles must satisfy 0 <= lower < upper <= 100",
+                ));
+            }
+            if !self.pairs.is_empty()
+                || self.direction_field.is_some()
+                || self.phase_portrait.is_some()
+                || self.conserved.is_some()
+            {
+                return Err(ScenarioError::invalid(
+                    "plot.ensemble",
+                    "draws its own chart, without pairs, a direction field, a phase portrait or a conserved quantity",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses the conserved quantity, if any.
+    pub fn conserved(&self, dimension: usize) -> Result<Option<Expr>, ScenarioError> {
+        self.conserved
+            .as_ref()
+            .map(|source| {
+                Expr::parse_with_dimension(source, dimension)
+                    .map_err(|e| ScenarioError::invalid("plot.conserved", e.annotate(source)))
+            })
+            .transpose()
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+            .unwrap_or_else(|| Backend::from_path(&self.output))
+    }
+
+    pub fn projections(&self, dimension: usize) -> Vec<Projection> {
+        if self.pairs.is_empty() {
+            (0..dimension).map(Projection::Component).collect()
+        } else {
+            self.pairs
+                .iter()
+                .map(|&(i, j)| Projection::Pair(i, j))
+                .collect()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    /// The file is not well-formed, or does not match the schema. The message
+    /// from the parser names the offending field.
+    Parse(String),
+    /// A field holds a value that cannot describe a sensible run.
+    Invalid {
+        field: String,
+        message: String,
+    },
+}
+
+impl ScenarioError {
+    fn invalid(field: impl Into<String>, message: impl Into<String>) -> Self {
+        ScenarioError::Invalid {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io(e) => write!(f, "{e}"),
+            ScenarioError::Parse(message) => write!(f, "{message}"),
+            ScenarioError::Invalid { field, message } => write!(f, "`{field}`: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+impl Scenario {
+    /// Reads a scenario from a `.toml` or `.json` file and validates it.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ScenarioError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(ScenarioError::Io)?;
+
+        let scenario: Scenario = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => {
+                // serde_json alone reports the line and column of an error,
+                // but not which field it was in
+                let json = &mut serde_json::Deserializer::from_str(&source);
+                serde_path_to_error::deserialize(json)
+                    .map_err(|e| ScenarioError::Parse(format!("`{}`: {}", e.path(), e.inner())))?
+            }
+            Some("toml") => {
+                toml::from_str(&source).map_err(|e| ScenarioError::Parse(e.to_string()))?
+            }
+            _ => {
+                return Err(ScenarioError::Parse(format!(
+                    "{}: scenario files must end in .toml or .json",
+                    path.display()
+                )))
+            }
+        };
+
+        scenario.validate()?;
+
+        Ok(scenario)
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.equations.len()
+    }
+
+    /// The x every trajectory starts at.
+    pub fn start_x(&self) -> f64 {
+        match self.initial {
+            InitialConditions::Spread { start_x, .. }
+            | InitialConditions::List { start_x, .. }
+            | InitialConditions::Grid { start_x, .. } => start_x,
+        }
+    }
+
+    /// Checks every field, reporting the first that is out of range.
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        let dimension = self.dimension();
+
+        if dimension == 0 {
+            return Err(ScenarioError::invalid(
+                "equations",
+                "at least one equation is required",
+            ));
+        }
+
+        self.system()?;
+
+        if !Method::NAMES.contains(&self.method.as_str()) {
+            return Err(ScenarioError::invalid(
+                "method",
+                format!(
+                    "unknown method `{}`, expected one of {}",
+                    self.method,
+                    Method::NAMES.join(", ")
+                ),
+            ));
+        }
+
+        if self.method().is_symplectic() && !dimension.is_multiple_of(2) {
+            return Err(ScenarioError::invalid(
+                "equations",
+                format!(
+                    "`{}` needs the positions and then the momenta, an even number of equations",
+                    self.method
+                ),
+            ));
+        }
+
+        if !(self.step != 0.0 && self.step.is_finite()) {
+            return Err(ScenarioError::invalid("step", "must be a nonzero number"));
+        }
+
+        self.validate_noise()?;
+        self.validate_delay()?;
+
+        let Tolerance { absolute, relative } = self.tolerance;
+        if absolute < 0.0 {
+            return Err(ScenarioError::invalid(
+                "tolerance.absolute",
+                "must not be negative",
+            ));
+        }
+        if relative < 0.0 {
+            return Err(ScenarioError::invalid(
+                "tolerance.relative",
+                "must not be negative",
+            ));
+        }
+        if absolute == 0.0 && relative == 0.0 {
+            return Err(ScenarioError::invalid(
+                "tolerance",
+                "`absolute` and `relative` cannot both be zero",
+            ));
+        }
+
+        if self.jacobian.is_some() && !self.method().is_implicit() {
+            return Err(ScenarioError::invalid(
+                "jacobian",
+                format!("only applies to implicit methods, not `{}`", self.method),
+            ));
+        }
+        self.jacobian()?;
+
+        self.validate_initial()?;
+        self.events()?;
+
+        if self.resample.is_some_and(|grid| grid.count == 0) {
+            return Err(ScenarioError::invalid(
+                "resample.count",
+                "must be at least 1",
+            ));
+        }
+
+        self.validate_end()?;
+
+        self.plot.validate(dimension)?;
+
+        Ok(())
+    }
+
+    fn validate_noise(&self) -> Result<(), ScenarioError> {
+        let stochastic = self.method().is_stochastic();
+        let Some(noise) = &self.noise else {
+            if stochastic {
+                return Err(ScenarioError::invalid(
+                    "noise",
+                    format!("`{}` needs the diffusion of each equation", self.method),
+                ));
+            }
+            if self.plot.ensemble.is_some() {
+                return Err(ScenarioError::invalid(
+                    "plot.ensemble",
+                    "needs noise to take sample paths from",
+                ));
+            }
+            return Ok(());
+        };
+
+        if !stochastic {
+            return Err(ScenarioError::invalid(
+                "noise",
+                format!("only applies to stochastic methods, not `{}`", self.method),
+            ));
+        }
+        if self.step < 0.0 || self.bidirectional {
+            return Err(ScenarioError::invalid(
+                if self.bidirectional {
+                    "bidirectional"
+                } else {
+                    "step"
+                },
+                "stochastic methods only integrate towards increasing x",
+            ));
+        }
+        if noise.paths == 0 {
+            return Err(ScenarioError::invalid("noise.paths", "must be at least 1"));
+        }
+        self.diffusion()?;
+
+        Ok(())
+    }
+
+    fn validate_delay(&self) -> Result<(), ScenarioError> {
+        let Some(delay) = &self.delay else {
+            return Ok(());
+        };
+
+        if delay.delays.is_empty() {
+            return Err(ScenarioError::invalid(
+                "delay.delays",
+                "at least one delay is required",
+            ));
+        }
+        for (i, tau) in delay.delays.iter().enumerate() {
+            if !(*tau > 0.0 && tau.is_finite()) {
+                return Err(ScenarioError::invalid(
+                    format!("delay.delays[{i}]"),
+                    "must be a positive number",
+                ));
+            }
+        }
+        if self.step < 0.0 || self.bidirectional {
+            return Err(ScenarioError::invalid(
+                if self.bidirectional {
+                    "bidirectional"
+                } else {
+                    "step"
+                },
+                "delay differential equations only integrate towards increasing x",
+            ));
+        }
+        if self.resample.is_some() {
+            return Err(ScenarioError::invalid(
+                "resample",
+                "has no dense output to draw on with delays",
+            ));
+        }
+        for (field, overlay) in [
+            ("plot.direction_field", self.plot.direction_field.is_some()),
+            ("plot.phase_portrait", self.plot.phase_portrait.is_some()),
+        ] {
+            if overlay {
+                return Err(ScenarioError::invalid(
+                    field,
+                    "the slope of a delay differential equation depends on its past",
+                ));
+            }
+        }
+        self.history()?;
+
+        Ok(())
+    }
+
+    fn validate_end(&self) -> Result<(), ScenarioError> {
+        let end = &self.end;
+        let positive = |field: &str, value: f64| {
+            if value > 0.0 && value.is_finite() {
+                Ok(())
+            } else {
+                Err(ScenarioError::invalid(
+                    format!("end.{field}"),
+                    "must be a positive number",
+                ))
+            }
+        };
+
+        if let Some(max_x) = end.max_x {
+            if max_x <= self.start_x() {
+                return Err(ScenarioError::invalid(
+                    "end.max_x",
+                    format!("must be greater than the initial x ({})", self.start_x()),
+                ));
+            }
+        }
+        if let Some(min_x) = end.min_x {
+            if min_x >= self.start_x() {
+                return Err(ScenarioError::invalid(
+                    "end.min_x",
+                    format!("must be less than the initial x ({})", self.start_x()),
+                ));
+            }
+        }
+        if let Some(max_abs_y) = end.max_abs_y {
+            positive("max_abs_y", max_abs_y)?;
+        }
+        if let Some(y_box) = &end.y_box {
+            if y_box.len() != self.dimension() {
+                return Err(ScenarioError::invalid(
+                    "end.y_box",
+                    format!(
+                        "has {} bounds, expected one per component ({})",
+                        y_box.len(),
+                        self.dimension()
+                    ),
+                ));
+            }
+            if let Some(i) = y_box.iter().position(|[lower, upper]| lower >= upper) {
+                return Err(ScenarioError::invalid(
+                    format!("end.y_box[{i}]"),
+                    "the lower bound must be less than the upper bound",
+                ));
+            }
+        }
+        if end.max_steps == Some(0) {
+            return Err(ScenarioError::invalid("end.max_steps", "must be positive"));
+        }
+        if let Some(time_limit) = end.time_limit {
+            positive("time_limit", time_limit)?;
+        }
+        if let Some(SteadyStateSettings { epsilon, steps }) = end.steady_state {
+            positive("steady_state.epsilon", epsilon)?;
+            if steps == 0 {
+                return Err(ScenarioError::invalid(
+                    "end.steady_state.steps",
+                    "must be positive",
+                ));
+            }
+        }
+
+        if self.limits().is_empty() {
+            return Err(ScenarioError::invalid(
+                "end",
+                "at least one limit is required",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn validate_initial(&self) -> Result<(), ScenarioError> {
+        let dimension = self.dimension();
+        let wrong_length = |field: String, length: usize| {
+            ScenarioError::invalid(
+                field,
+                format!("has {length} value(s) but there are {dimension} equation(s)"),
+            )
+        };
+
+        match &self.initial {
+            InitialConditions::Spread { start_y, count, .. } => {
+                if start_y.len() != dimension {
+                    return Err(wrong_length(
+                        "initial.spread.start_y".to_string(),
+                        start_y.len(),
+                    ));
+                }
+                if *count == 0 {
+                    return Err(ScenarioError::invalid(
+                        "initial.spread.count",
+                        "must be at least 1",
+                    ));
+                }
+            }
+            InitialConditions::List { y, .. } => {
+                if y.is_empty() {
+                    return Err(ScenarioError::invalid(
+                        "initial.list.y",
+                        "at least one initial value is required",
+                    ));
+                }
+                for (i, y) in y.iter().enumerate() {
+                    if y.len() != dimension {
+                        return Err(wrong_length(format!("initial.list.y[{i}]"), y.len()));
+                    }
+                }
+            }
+            InitialConditions::Grid { axes, .. } => {
+                if axes.len() != dimension {
+                    return Err(wrong_length("initial.grid.axes".to_string(), axes.len()));
+                }
+                for (i, axis) in axes.iter().enumerate() {
+                    if axis.count == 0 {
+                        return Err(ScenarioError::invalid(
+                            format!("initial.grid.axes[{i}].count"),
+                            "must be at least 1",
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses the equations, naming the first that fails. With delays, the
+    /// system takes the current state followed by the delayed ones.
+    pub fn system(&self) -> Result<ExprSystem, ScenarioError> {
+        let delays = self.delay.as_ref().map_or(0, |delay| delay.delays.len());
+        ExprSystem::parse_with_delays(&self.equations, delays).map_err(|(i, e)| {
+            ScenarioError::invalid(format!("equations[{i}]"), e.annotate(&self.equations[i]))
+        })
+    }
+
+    /// The events of the run, in the order they are listed.
+    pub fn events(&self) -> Result<Vec<Event<'static>>, ScenarioError> {
+        self.events
+            .iter()
+            .enumerate()
+            .map(|(i, settings)| {
+                let expression = Expr::parse_with_dimension(&settings.expression, self.dimension())
+                    .map_err(|e| {
+                        ScenarioError::invalid(
+                            format!("events[{i}].expression"),
+                            e.annotate(&settings.expression),
+                        )
+                    })?;
+
+                Ok(Event::new(
+                    move |x, y| expression.eval(x, y),
+                    settings.direction,
+                    settings.action,
+                ))
+            })
+            .collect()
+    }
+
+    /// Parses the diffusion of the noise, if any, naming the first
+    /// component that fails.
+    pub fn diffusion(&self) -> Result<Option<Arc<DiffusionFunction<'static>>>, ScenarioError> {
+        let Some(noise) = &self.noise else {
+            return Ok(None);
+        };
+        let dimension = self.dimension();
+        if noise.diffusion.len() != dimension {
+            return Err(ScenarioError::invalid(
+                "noise.diffusion",
+                format!("expected {dimension} expressions, one per equation"),
+            ));
+        }
+
+        let diffusion = ExprSystem::parse(&noise.diffusion).map_err(|(i, e)| {
+            ScenarioError::invalid(
+                format!("noise.diffusion[{i}]"),
+                e.annotate(&noise.diffusion[i]),
+            )
+        })?;
+        Ok(Some(Arc::new(move |x, y, g| diffusion.derivative(x, y, g))))
+    }
+
+    /// Parses the history of a delay differential equation, if given,
+    /// naming the first component that fails.
+    pub fn history(&self) -> Result<Option<Vec<Expr>>, ScenarioError> {
+        let Some(history) = self.delay.as_ref().and_then(|delay| delay.history.as_ref()) else {
+            return Ok(None);
+        };
+        let dimension = self.dimension();
+        if history.len() != dimension {
+            return Err(ScenarioError::invalid(
+                "delay.history",
+                format!("expected {dimension} expressions, one per equation"),
+            ));
+        }
+
+        history
+            .iter()
+            .enumerate()
+            .map(|(i, source)| {
+                // the history is a function of x alone
+                Expr::parse_with_dimension(source, 0).map_err(|e| {
+                    ScenarioError::invalid(format!("delay.history[{i}]"), e.annotate(source))
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+
+    /// Parses the Jacobian, if given, naming the first entry that fails.
+    pub fn jacobian(&self) -> Result<Option<Arc<JacobianFunction<'static>>>, ScenarioError> {
+        let Some(rows) = &self.jacobian else {
+            return Ok(None);
+        };
+        let dimension = self.dimension();
+        if rows.len() != dimension {
+            return Err(ScenarioError::invalid(
+                "jacobian",
+                format!("expected {dimension} rows, one per equation"),
+            ));
+        }
+
+        let entries = rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                if row.len() != dimension {
+                    return Err(ScenarioError::invalid(
+                        format!("jacobian[{i}]"),
+                        format!("expected {dimension} entries, one per component"),
+                    ));
+                }
+                row.iter()
+                    .enumerate()
+                    .map(|(j, source)| {
+                        Expr::parse_with_dimension(source, dimension).map_err(|e| {
+                            ScenarioError::invalid(
+                                format!("jacobian[{i}][{j}]"),
+                                e.annotate(source),
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Some(Arc::new(move |x, y| {
+            entries
+                .iter()
+                .map(|row| row.iter().map(|entry| entry.eval(x, y)).collect())
+                .collect()
+        })))
+    }
+
+    pub fn method(&self) -> Method {
+        Method::from_name(&self.method, self.tolerance).unwrap_or(Method::Euler)
+    }
+
+    fn limits(&self) -> Vec<Box<dyn Termination + Send>> {
+        let end = &self.end;
+        let mut limits: Vec<Box<dyn Termination + Send>> = vec![];
+
+        let x_range = self.x_range();
+        if x_range.min.is_some() || x_range.max.is_some() {
+            limits.push(Box::new(x_range));
+        }
+        if let Some(max_abs_y) = end.max_abs_y {
+            limits.push(Box::new(YBox::symmetric(max_abs_y, self.dimension())));
+        }
+        if let Some(y_box) = &end.y_box {
+            limits.push(Box::new(YBox {
+                lower: y_box.iter().map(|[lower, _]| *lower).collect(),
+                upper: y_box.iter().map(|[_, upper]| *upper).collect(),
+            }));
+        }
+        if let Some(max_steps) = end.max_steps {
+            limits.push(Box::new(MaxSteps(max_steps)));
+        }
+        if let Some(time_limit) = end.time_limit {
+            limits.push(Box::new(WallClock::new(Duration::from_secs_f64(
+                time_limit,
+            ))));
+        }
+        if let Some(SteadyStateSettings { epsilon, steps }) = end.steady_state {
+            limits.push(Box::new(SteadyState::new(epsilon, steps)));
+        }
+
+        limits
+    }
+
+    /// Whether any trajectory heads towards decreasing x.
+    pub fn integrates_backwards(&self) -> bool {
+        self.step < 0.0 || self.bidirectional
+    }
+
+    /// The limits on x: those given, and otherwise 150 past the initial x
+    /// in each direction it is integrated. Combined with `all`, such a
+    /// default could keep the other limits from ever ending a trajectory,
+    /// so there is none.
+    pub fn x_range(&self) -> XRange {
+        let start_x = self.start_x();
+        let default = |integrated: bool, span: f64| {
+            (integrated && self.end.combine == Combine::Any).then_some(start_x + span)
+        };
+        let forwards = self.step > 0.0 || self.bidirectional;
+
+        XRange {
+            min: (self.end.min_x).or_else(|| default(self.integrates_backwards(), -DEFAULT_X_SPAN)),
+            max: (self.end.max_x).or_else(|| default(forwards, DEFAULT_X_SPAN)),
+        }
+    }
+
+    /// A fresh termination for one trajectory, combining every limit.
+    pub fn termination(&self) -> Box<dyn Termination + Send> {
+        match self.end.combine {
+            Combine::Any => Box::new(Any(self.limits())),
+            Combine::All => Box::new(All(self.limits())),
+        }
+    }
+
+    /// The starting point of every trajectory, repeated once per sample
+    /// path when the equations are driven by noise.
+    pub fn initial_points(&self) -> Vec<Point> {
+        let paths = self.noise.as_ref().map_or(1, |noise| noise.paths);
+        self.starts()
+            .into_iter()
+            .flat_map(|start| std::iter::repeat_n(start, paths))
+            .collect()
+    }
+
+    fn starts(&self) -> Vec<Point> {
+        let start_x = self.start_x();
+
+        match &self.initial {
+            InitialConditions::Spread {
+                start_y,
+                y_spread,
+                count,
+                ..
+            } => (0..*count)
+                .map(|i| {
+                    let mut y = start_y.clone();
+                    y[0] += i as f64 * y_spread;
+                    (start_x, y).into()
+                })
+                .collect(),
+            InitialConditions::List { y, .. } => {
+                y.iter().map(|y| (start_x, y.clone()).into()).collect()
+            }
+            InitialConditions::Grid { axes, .. } => Axis::grid(axes)
+                .into_iter()
+                .map(|y| (start_x, y).into())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Scenario, ScenarioError> {
+        let scenario: Scenario =
+            toml::from_str(source).map_err(|e| ScenarioError::Parse(e.to_string()))?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    fn invalid_field(source: &str) -> String {
+        match parse(source) {
+            Err(ScenarioError::Invalid { field, .. }) => field,
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn grid_initial_conditions() {
+        let scenario = parse(
+            r#"
+            equations = ["y1", "-y0"]
+            method = "rk4"
+
+            [initial.grid]
+            start_x = 1.0
+            axes = [{ from = 0.0, to = 1.0, count = 3 }, { from = 5.0, to = 6.0, count = 2 }]
+            "#,
+        )
+        .unwrap();
+
+        let points = scenario.initial_points();
+        assert_eq!(points.len(), 6);
+        assert_eq!(points[0], (1.0, [0.0, 5.0]).into());
+        assert_eq!(points[5], (1.0, [1.0, 6.0]).into());
+        assert_eq!(scenario.method(), Method::RungeKutta4);
+    }
+
+    #[test]
+    fn json_matches_toml() {
+        let toml = parse(
+            r#"
+            equations = ["cbrt(y) + x"]
+
+            [initial.spread]
+            start_x = 0.0
+            start_y = [0.0]
+            y_spread = 10.0
+            count = 10
+            "#,
+        )
+        .unwrap();
+
+        let json: Scenario = serde_json::from_str(
+            r#"{
+                "equations": ["cbrt(y) + x"],
+                "initial": { "spread": { "start_x": 0.0, "start_y": [0.0], "y_spread": 10.0, "count": 10 } }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(toml, json);
+        assert_eq!(toml.initial_points()[9], (0.0, 90.0).into());
+    }
+
+    #[test]
+    fn default_x_limits_follow_the_direction() {
+        let source = |start_x: f64, step: f64| {
+            format!(
+                r#"
+                equations = ["cos(x)"]
+                method = "rk4"
+                step = {step}
+
+                [initial.spread]
+                start_x = {start_x}
+                start_y = [0.0]
+                y_spread = 0.0
+                count = 1
+                "#
+            )
+        };
+        let x_range = |source: &str| {
+            let XRange { min, max } = parse(source).unwrap().x_range();
+            (min, max)
+        };
+
+        assert_eq!(x_range(&source(0.0, 0.5)), (None, Some(150.0)));
+        // backwards from beyond the forward default, and from below -150
+        assert_eq!(x_range(&source(200.0, -0.5)), (Some(50.0), None));
+        assert_eq!(x_range(&source(-200.0, -0.5)), (Some(-350.0), None));
+        let both = source(0.0, 0.5).replace("method", "bidirectional = true\nmethod");
+        assert_eq!(x_range(&both), (Some(-150.0), Some(150.0)));
+        let given = source(200.0, -0.5) + "[end]\nmin_x = 190.0";
+        assert_eq!(x_range(&given), (Some(190.0), None));
+        let all = source(200.0, -0.5) + "[end]\ncombine = \"all\"\nmax_steps = 10";
+        assert_eq!(x_range(&all), (None, None));
+
+        // y = sin(x) stays bounded, so only the default limit on x ends it
+        let scenario = parse(&source(200.0, -0.5)).unwrap();
+        let system = scenario.system().unwrap();
+        let trajectory = crate::integrate(
+            scenario.initial_points().remove(0),
+            scenario.step,
+            scenario.method().stepper().as_mut(),
+            scenario.termination(),
+            &[],
+            |x, y, dy| system.derivative(x, y, dy),
+        );
+        assert!((trajectory.points.last().unwrap().x - 50.0).abs() < 1e-9);
+        assert!(matches!(trajectory.stop, crate::Stop::Condition(_)));
+    }
+
+    #[test]
+    fn unknown_field_is_named() {
+        let error = parse(
+            r#"
+            equations = ["y"]
+            stpe = 0.1
+
+            [initial.list]
+            start_x = 0.0
+            y = [[1.0]]
+            "#,
+        )
+        .unwrap_err();
+
+        assert!(error.to_string().contains("stpe"), "{error}");
+    }
+
+    #[test]
+    fn invalid_fields_are_named() {
+        let with = |extra: &str| {
+            format!(
+                "equations = [\"y1\", \"-y0\"]\n{extra}\n[initial.list]\nstart_x = 0.0\ny = [[1.0, 0.0]]\n"
+            )
+        };
+
+        assert_eq!(invalid_field(&with("step = 0.0")), "step");
+        assert_eq!(invalid_field(&with("method = \"rk5\"")), "method");
+        assert_eq!(
+            invalid_field(&with("[tolerance]\nabsolute = -1.0")),
+            "tolerance.absolute"
+        );
+        assert_eq!(invalid_field(&with("[end]\nmax_x = -1.0")), "end.max_x");
+        assert_eq!(invalid_field(&with("[end]\nmin_x = 1.0")), "end.min_x");
+        assert_eq!(
+            invalid_field(&with("[end]\ny_box = [[0.0, 1.0], [2.0, -2.0]]")),
+            "end.y_box[1]"
+        );
+        assert_eq!(
+            invalid_field(&with("[end.steady_state]\nepsilon = 0.0")),
+            "end.steady_state.epsilon"
+        );
+        assert_eq!(
+            invalid_field(&with("[end]\nmax_x = 1.0\nmax_abs_y = 0.0")),
+            "end.max_abs_y"
+        );
+        assert_eq!(
+            invalid_field(&with("jacobian = [[\"0\", \"1\"], [\"-1\", \"0\"]]")),
+            "jacobian"
+        );
+        assert_eq!(
+            invalid_field(&with(
+                "method = \"bdf2\"\njacobian = [[\"0\", \"1\"], [\"-1\", \"y2\"]]"
+            )),
+            "jacobian[1][1]"
+        );
+        assert_eq!(
+            invalid_field(&with(
+                "method = \"backward-euler\"\njacobian = [[\"0\", \"1\"], [\"-1\"]]"
+            )),
+            "jacobian[1]"
+        );
+        assert_eq!(
+            invalid_field(&with("[plot]\nconserved = \"y0^2 + y2^2\"")),
+            "plot.conserved"
+        );
+        assert_eq!(
+            invalid_field("equations = [\"y\"]\nmethod = \"verlet\"\n[initial.list]\nstart_x = 0.0\ny = [[1.0]]"),
+            "equations"
+        );
+        assert_eq!(invalid_field(&with("method = \"milstein\"")), "noise");
+        assert_eq!(
+            invalid_field(&with(
+                "method = \"milstein\"\n[noise]\ndiffusion = [\"0.1\", \"sin(\"]"
+            )),
+            "noise.diffusion[1]"
+        );
+        assert_eq!(
+            invalid_field(&with(
+                "method = \"milstein\"\n[noise]\ndiffusion = [\"0.1\", \"0.1\"]\n[plot.ensemble]\nlower = 50.0\nupper = 10.0"
+            )),
+            "plot.ensemble"
+        );
+        assert_eq!(
+            invalid_field(&with("[delay]\ndelays = [1.0, 0.0]")),
+            "delay.delays[1]"
+        );
+        assert_eq!(
+            invalid_field(&with("[delay]\ndelays = [1.0]\nhistory = [\"1\", \"y\"]")),
+            "delay.history[1]"
+        );
+        assert_eq!(
+            invalid_field(&with("bidirectional = true\n[delay]\ndelays = [1.0]")),
+            "bidirectional"
+        );
+        assert_eq!(
+            invalid_field(&with("step = -0.1\n[delay]\ndelays = [1.0]")),
+            "step"
+        );
+        assert_eq!(
+            invalid_field("equations = [\"-y_1\"]\n[delay]\ndelays = [1.0]\n[initial.list]\nstart_x = 0.0\ny = [[1.0]]"),
+            "equations[0]"
+        );
+        assert_eq!(
+            invalid_field(&with("[plot]\npairs = [[0, 2]]")),
+            "plot.pairs[0]"
+        );
+        assert_eq!(
+            invalid_field(
+                "equations = [\"y\", \"z\"]\n[initial.list]\nstart_x = 0.0\ny = [[1.0, 0.0]]"
+            ),
+            "equations[1]"
+        );
+        assert_eq!(
+            invalid_field("equations = [\"y\"]\n[initial.list]\nstart_x = 0.0\ny = [[1.0, 0.0]]"),
+            "initial.list.y[0]"
+        );
+    }
+}
@@ -0,0 +1,579 @@
+//! Two-point boundary value problems, with some components of `y` given at
+//! the start `a` of an interval and the others at its end `b`, solved by
+//! shooting.
+//!
+//! The components not given at `a` are unknowns. Each guess of them is an
+//! initial value problem, integrated across the interval, and the guess is
+//! corrected until the end of the trajectory meets the conditions at `b`.
+//! The corrections are secant updates of the Jacobian of the mismatch
+//! (Broyden's method), falling back to a Newton step on a Jacobian from
+//! finite differences whenever they stop making progress.
+//!
+//! Problems whose trajectories are very sensitive to their initial values
+//! can be split into segments, each integrated from a state of its own
+//! (multiple shooting). The states at the interior nodes become unknowns as
+//! well, and the mismatch includes the gap where each segment ends short of
+//! the start of the next.
+
+use std::fmt;
+
+use crate::{equilibrium::solve, integrate, EndCondition, Point, Stepper, Stop};
+
+/// The most halvings of a correction before it is given up on.
+const MAX_HALVINGS: usize = 10;
+
+/// A boundary value problem on the interval from `a` to `b`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundaryValueProblem {
+    /// `[a, b]`, with `a < b`.
+    pub interval: [f64; 2],
+    /// The components given at `a`, with their values.
+    pub start: Vec<(usize, f64)>,
+    /// The components given at `b`, with their values. There must be as
+    /// many as there are components missing from `start`.
+    pub end: Vec<(usize, f64)>,
+    /// A guess of the whole state at `a`; the components given there are
+    /// replaced with their values.
+    pub guess: Vec<f64>,
+}
+
+/// How to shoot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shooting {
+    /// The step size of every integration, positive.
+    pub step_size: f64,
+    /// The number of segments the interval is split into, 1 for single
+    /// shooting.
+    pub segments: usize,
+    /// The largest mismatch, in any component, accepted as a solution.
+    pub tolerance: f64,
+    pub max_iterations: usize,
+}
+
+impl Default for Shooting {
+    fn default() -> Self {
+        Shooting {
+            step_size: 0.01,
+            segments: 1,
+            tolerance: 1e-8,
+            max_iterations: 50,
+        }
+    }
+}
+
+/// A solved boundary value problem.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution {
+    /// The solution from `a` to `b`, the segments joined end to end.
+    pub points: Vec<Point>,
+    /// The corrections it took.
+    pub iterations: usize,
+    /// The largest mismatch left.
+    pub mismatch: f64,
+}
+
+/// Why shooting failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShootingError {
+    /// The problem itself is malformed, as described.
+    Invalid(String),
+    /// The trajectory of a segment ended before reaching its end, at the
+    /// initial guess or while computing the Jacobian.
+    Unreachable {
+        segment: usize,
+        from: f64,
+        to: f64,
+        stop: Stop,
+    },
+    /// The Jacobian of the mismatch has no inverse.
+    Singular { iteration: usize },
+    /// Neither a secant nor a Newton correction reduces the mismatch.
+    Stalled { iteration: usize, mismatch: f64 },
+    /// The mismatch is still above the tolerance after every iteration.
+    NotConverged { iterations: usize, mismatch: f64 },
+}
+
+impl fmt::Display for ShootingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let advice = "try another guess or more segments";
+        match self {
+            ShootingError::Invalid(message) => f.write_str(message),
+            ShootingError::Unreachable {
+                segment,
+                from,
+                to,
+                stop,
+            } => write!(
+                f,
+                "the trajectory of segment {segment} from x = {from} was stopped by {stop} before reaching x = {to}; {advice}"
+            ),
+            ShootingError::Singular { iteration } => write!(
+                f,
+                "the mismatch does not depend on every unknown at iteration {iteration}; {advice}"
+            ),
+            ShootingError::Stalled {
+                iteration,
+                mismatch,
+            } => write!(
+                f,
+                "stalled at iteration {iteration} with a mismatch of {mismatch}; {advice}"
+            ),
+            ShootingError::NotConverged {
+                iterations,
+                mismatch,
+            } => write!(
+                f,
+                "no convergence after {iterations} iterations, with a mismatch of {mismatch}; {advice}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ShootingError {}
+
+/// The unknowns of a problem and the mismatch they leave.
+struct Shots<'a, S: ?Sized, F> {
+    problem: &'a BoundaryValueProblem,
+    settings: &'a Shooting,
+    stepper: &'a mut S,
+    derivative: F,
+    /// The components missing at `a`.
+    unknown: Vec<usize>,
+    /// The start of each segment, and `b`.
+    nodes: Vec<f64>,
+}
+
+impl<S: Stepper + ?Sized, F: Fn(f64, &[f64], &mut [f64])> Shots<'_, S, F> {
+    fn dimension(&self) -> usize {
+        self.problem.guess.len()
+    }
+
+    /// The state at the start of each segment for the unknowns `z`: those
+    /// at `a` first, then the whole state at each interior node.
+    fn starts(&self, z: &[f64]) -> Vec<Point> {
+        let mut y = self.problem.guess.clone();
+        for &(i, value) in &self.problem.start {
+            y[i] = value;
+        }
+        for (&i, value) in self.unknown.iter().zip(z) {
+            y[i] = *value;
+        }
+
+        let interior = z[self.unknown.len()..].chunks(self.dimension());
+        let states = std::iter::once(y).chain(interior.map(<[f64]>::to_vec));
+        self.nodes
+            .iter()
+            .zip(states)
+            .map(|(&x, y)| Point { x, y })
+            .collect()
+    }
+
+    /// Integrates every segment, or reports the first that falls short.
+    fn shoot(&mut self, z: &[f64]) -> Result<Vec<Vec<Point>>, ShootingError> {
+        let starts = self.starts(z);
+        let mut segments = Vec::with_capacity(starts.len());
+
+        for (segment, start) in starts.into_iter().enumerate() {
+            let (from, to) = (start.x, self.nodes[segment + 1]);
+            let end_condition = EndCondition::until(to);
+            let trajectory = integrate(
+                start,
+                self.settings.step_size,
+                &mut *self.stepper,
+                end_condition,
+                &[],
+                &self.derivative,
+            );
+
+            // a trajectory ends before its first degenerate point
+            let reached = trajectory
+                .last()
+                .is_some_and(|last| (last.x - to).abs() <= 1e-9 * (1.0 + to.abs()));
+            if !reached {
+                return Err(ShootingError::Unreachable {
+                    segment,
+                    from,
+                    to,
+                    stop: trajectory.stop,
+                });
+            }
+            segments.push(trajectory.points);
+        }
+
+        Ok(segments)
+    }
+
+    /// The gap at each interior node, then the miss at `b`.
+    fn mismatch(&self, segments: &[Vec<Point>], z: &[f64]) -> Vec<f64> {
+        let ends: Vec<&Vec<f64>> = segments
+            .iter()
+            .flat_map(|points| points.last())
+            .map(|end| &end.y)
+            .collect();
+        let Some((last, ends)) = ends.split_last() else {
+            return vec![];
+        };
+        let interior = z[self.unknown.len()..].chunks(self.dimension());
+
+        let mut mismatch: Vec<f64> = ends
+            .iter()
+            .zip(interior)
+            .flat_map(|(end, next)| end.iter().zip(next).map(|(a, b)| a - b))
+            .collect();
+        mismatch.extend(self.problem.end.iter().map(|&(i, value)| last[i] - value));
+        mismatch
+    }
+
+    fn residual(&mut self, z: &[f64]) -> Result<(Vec<f64>, Vec<Vec<Point>>), ShootingError> {
+        let segments = self.shoot(z)?;
+        Ok((self.mismatch(&segments, z), segments))
+    }
+
+    /// The Jacobian of the mismatch at `z` by forward differences.
+    fn jacobian(&mut self, z: &[f64], mismatch: &[f64]) -> Result<Vec<Vec<f64>>, ShootingError> {
+        let n = z.len();
+        let mut jacobian = vec![vec![0.0; n]; n];
+        let mut shifted = z.to_vec();
+
+        for j in 0..n {
+            let h = f64::EPSILON.sqrt() * z[j].abs().max(1.0);
+            shifted[j] = z[j] + h;
+            let (column, _) = self.residual(&shifted)?;
+            shifted[j] = z[j];
+
+            for i in 0..n {
+                jacobian[i][j] = (column[i] - mismatch[i]) / h;
+            }
+        }
+
+        Ok(jacobian)
+    }
+}
+
+fn largest(v: &[f64]) -> f64 {
+    v.iter().fold(0.0, |max: f64, v| max.max(v.abs()))
+}
+
+/// Solves `problem` for the system `y' = derivative(x, y)`, integrating
+/// with `stepper` as [`integrate`] does.
+pub fn shoot<S: Stepper + ?Sized>(
+    problem: &BoundaryValueProblem,
+    settings: &Shooting,
+    stepper: &mut S,
+    derivative: impl Fn(f64, &[f64], &mut [f64]),
+) -> Result<Solution, ShootingError> {
+    let unknown = validate(problem, settings)?;
+    let [a, b] = problem.interval;
+    let nodes: Vec<f64> = (0..=settings.segments)
+        .map(|s| a + (b - a) * s as f64 / settings.segments as f64)
+        .collect();
+
+    let mut shots = Shots {
+        problem,
+        settings,
+        stepper,
+        derivative,
+        unknown,
+        nodes,
+    };
+    let mut z = initial_unknowns(problem, &shots.unknown, &shots.nodes);
+    let (mut mismatch, mut segments) = shots.residual(&z)?;
+    let mut jacobian = None;
+
+    for iteration in 0..=settings.max_iterations {
+        if largest(&mismatch) <= settings.tolerance {
+            return Ok(Solution {
+                points: join(segments),
+                iterations: iteration,
+                mismatch: largest(&mismatch),
+            });
+        }
+        if iteration == settings.max_iterations {
+            break;
+        }
+
+        // a fresh Jacobian for the first correction and after a secant
+        // correction fails
+        let fresh = jacobian.is_none();
+        let matrix = match jacobian.take() {
+            Some(matrix) => matrix,
+            None => shots.jacobian(&z, &mismatch)?,
+        };
+        let step = solve(matrix.clone(), mismatch.iter().map(|m| -m).collect())
+            .ok_or(ShootingError::Singular { iteration })?;
+
+        let mut scale = 1.0;
+        let accepted = loop {
+            let next: Vec<f64> = z.iter().zip(&step).map(|(z, s)| z + scale * s).collect();
+            if let Ok((next_mismatch, next_segments)) = shots.residual(&next) {
+                if largest(&next_mismatch) < largest(&mismatch) {
+                    break Some((next, next_mismatch, next_segments));
+                }
+            }
+            if scale < 0.5f64.powi(MAX_HALVINGS as i32) {
+                break None;
+            }
+            scale /= 2.0;
+        };
+
+        let Some((next, next_mismatch, next_segments)) = accepted else {
+            if fresh {
+                return Err(ShootingError::Stalled {
+                    iteration,
+                    mismatch: largest(&mismatch),
+                });
+            }
+            continue;
+        };
+
+        jacobian = Some(broyden(matrix, &z, &next, &mismatch, &next_mismatch));
+        (z, mismatch, segments) = (next, next_mismatch, next_segments);
+    }
+
+    Err(ShootingError::NotConverged {
+        iterations: settings.max_iterations,
+        mismatch: largest(&mismatch),
+    })
+}
+
+/// Checks that `problem` has as many conditions as unknowns, returning the
+/// components missing at `a`.
+fn validate(
+    problem: &BoundaryValueProblem,
+    settings: &Shooting,
+) -> Result<Vec<usize>, ShootingError> {
+    let invalid = |message: String| Err(ShootingError::Invalid(message));
+    let n = problem.guess.len();
+    let [a, b] = problem.interval;
+
+    if !(a < b && a.is_finite() && b.is_finite()) {
+        return invalid(format!(
+            "the interval from {a} to {b} must be finite and increasing"
+        ));
+    }
+    if !(settings.step_size > 0.0 && settings.segments > 0) {
+        return invalid("the step size and the number of segments must be positive".to_string());
+    }
+    for (side, conditions) in [("a", &problem.start), ("b", &problem.end)] {
+        for (k, &(i, _)) in conditions.iter().enumerate() {
+            if i >= n {
+                return invalid(format!(
+                    "component {i} is given at {side}, but the system only has {n} component(s)"
+                ));
+            }
+            if conditions[..k].iter().any(|&(j, _)| j == i) {
+                return invalid(format!("component {i} is given twice at {side}"));
+            }
+        }
+    }
+
+    let unknown: Vec<usize> = (0..n)
+        .filter(|i| !problem.start.iter().any(|(j, _)| j == i))
+        .collect();
+    if unknown.len() != problem.end.len() {
+        return invalid(format!(
+            "{} component(s) are left free at a, which needs as many given at b, not {}",
+            unknown.len(),
+            problem.end.len()
+        ));
+    }
+
+    Ok(unknown)
+}
+
+/// The guessed components missing at `a`, then the state at each interior
+/// node. The nodes start from the state at `a`, with the components given at
+/// `b` on the straight line to their value there: the trajectory of the
+/// guess is no better a start for the problems that need several segments.
+fn initial_unknowns(problem: &BoundaryValueProblem, unknown: &[usize], nodes: &[f64]) -> Vec<f64> {
+    let mut start = problem.guess.clone();
+    for &(i, value) in &problem.start {
+        start[i] = value;
+    }
+    let mut z: Vec<f64> = unknown.iter().map(|&i| start[i]).collect();
+
+    let [a, b] = problem.interval;
+    for &x in &nodes[1..nodes.len() - 1] {
+        let mut y = start.clone();
+        let fraction = (x - a) / (b - a);
+        for &(i, value) in &problem.end {
+            y[i] = start[i] + fraction * (value - start[i]);
+        }
+        z.extend(y);
+    }
+    z
+}
+
+/// Broyden's rank-one update of `jacobian` to the secant through the last
+/// correction from `z` to `next`.
+fn broyden(
+    mut jacobian: Vec<Vec<f64>>,
+    z: &[f64],
+    next: &[f64],
+    mismatch: &[f64],
+    next_mismatch: &[f64],
+) -> Vec<Vec<f64>> {
+    let step: Vec<f64> = next.iter().zip(z).map(|(a, b)| a - b).collect();
+    let length = step.iter().map(|s| s * s).sum::<f64>();
+    if length == 0.0 {
+        return jacobian;
+    }
+
+    for (i, row) in jacobian.iter_mut().enumerate() {
+        let predicted: f64 = row.iter().zip(&step).map(|(j, s)| j * s).sum();
+        let miss = next_mismatch[i] - mismatch[i] - predicted;
+        for (entry, s) in row.iter_mut().zip(&step) {
+            *entry += miss * s / length;
+        }
+    }
+    jacobian
+}
+
+/// Joins the segments of a solution, keeping each interior node once.
+fn join(segments: Vec<Vec<Point>>) -> Vec<Point> {
+    let mut points = vec![];
+    for segment in segments {
+        let shared = usize::from(!points.is_empty());
+        points.extend(segment.into_iter().skip(shared));
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RungeKutta4;
+
+    /// y'' = -y as y0' = y1, y1' = -y0.
+    fn oscillator(_: f64, y: &[f64], dy: &mut [f64]) {
+        dy[0] = y[1];
+        dy[1] = -y[0];
+    }
+
+    #[test]
+    fn finds_the_initial_slope() {
+        // y(0) = 0, y(1) = 1 is solved by sin(x) / sin(1)
+        let problem = BoundaryValueProblem {
+            interval: [0.0, 1.0],
+            start: vec![(0, 0.0)],
+            end: vec![(0, 1.0)],
+            guess: vec![0.0, 0.0],
+        };
+        let solution = shoot(
+            &problem,
+            &Shooting::default(),
+            &mut RungeKutta4::default(),
+            oscillator,
+        )
+        .unwrap();
+
+        let slope = solution.points[0].y[1];
+        assert!((slope - 1.0 / 1f64.sin()).abs() < 1e-7, "{slope}");
+        let last = solution.points.last().unwrap();
+        assert!((last.x - 1.0).abs() < 1e-9 && (last.y[0] - 1.0).abs() < 1e-8);
+        // the mismatch is linear in the slope, so the first Newton step lands
+        assert!(solution.iterations <= 2, "{}", solution.iterations);
+    }
+
+    #[test]
+    fn multiple_shooting_tames_a_sensitive_problem() {
+        // y'' = 400 y with y(0) = 1 and y(4) = 0: a wrong slope at 0 grows
+        // like e^80, beyond what one shot can correct
+        let stiff = |_: f64, y: &[f64], dy: &mut [f64]| {
+            dy[0] = y[1];
+            dy[1] = 400.0 * y[0];
+        };
+        let problem = BoundaryValueProblem {
+            interval: [0.0, 4.0],
+            start: vec![(0, 1.0)],
+            end: vec![(0, 0.0)],
+            guess: vec![1.0, 0.0],
+        };
+
+        let single = Shooting {
+            step_size: 0.005,
+            ..Shooting::default()
+        };
+        assert!(shoot(&problem, &single, &mut RungeKutta4::default(), stiff).is_err());
+
+        let multiple = Shooting {
+            segments: 40,
+            ..single
+        };
+        let solution = shoot(&problem, &multiple, &mut RungeKutta4::default(), stiff).unwrap();
+        // very nearly e^(-20 x), whose slope at 0 is -20
+        assert!((solution.points[0].y[1] + 20.0).abs() < 1e-3);
+        for point in &solution.points {
+            assert!(
+                (point.y[0] - (-20.0 * point.x).exp()).abs() < 1e-4,
+                "{point:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn nonlinear_problem() {
+        // Bratu's problem y'' = -e^y, y(0) = y(1) = 0, has a lower solution
+        // with y'(0) of about 0.5493
+        let bratu = |_: f64, y: &[f64], dy: &mut [f64]| {
+            dy[0] = y[1];
+            dy[1] = -y[0].exp();
+        };
+        let problem = BoundaryValueProblem {
+            interval: [0.0, 1.0],
+            start: vec![(0, 0.0)],
+            end: vec![(0, 0.0)],
+            guess: vec![0.0, 0.0],
+        };
+        let solution = shoot(
+            &problem,
+            &Shooting::default(),
+            &mut RungeKutta4::default(),
+            bratu,
+        )
+        .unwrap();
+        assert!((solution.points[0].y[1] - 0.5493).abs() < 1e-3);
+    }
+
+    #[test]
+    fn failures_are_reported() {
+        let problem = BoundaryValueProblem {
+            interval: [0.0, 1.0],
+            start: vec![(0, 0.0)],
+            end: vec![(0, 1.0), (1, 0.0)],
+            guess: vec![0.0, 0.0],
+        };
+        let error = shoot(
+            &problem,
+            &Shooting::default(),
+            &mut RungeKutta4::default(),
+            oscillator,
+        )
+        .unwrap_err();
+        assert!(matches!(error, ShootingError::Invalid(_)), "{error}");
+
+        // y(0) = 0 and y(pi) = 1 has no solution, every sin(x) multiple
+        // ending at 0
+        let problem = BoundaryValueProblem {
+            interval: [0.0, std::f64::consts::PI],
+            start: vec![(0, 0.0)],
+            end: vec![(0, 1.0)],
+            guess: vec![0.0, 1.0],
+        };
+        let error = shoot(
+            &problem,
+            &Shooting::default(),
+            &mut RungeKutta4::default(),
+            oscillator,
+        )
+        .unwrap_err();
+        assert!(
+            matches!(
+                error,
+                ShootingError::Singular { .. } | ShootingError::Stalled { .. }
+            ),
+            "{error}"
+        );
+        assert!(error.to_string().contains("try another guess"));
+    }
+}
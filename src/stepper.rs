@@ -0,0 +1,321 @@
+use std::sync::Arc;
+
+use crate::{
+    adaptive::DormandPrince,
+    dense::Continuous,
+    implicit::{BackwardEuler, Bdf, CrankNicolson, JacobianFunction},
+    multistep::AdamsBashforthMoulton,
+    stochastic::{EulerMaruyama, Milstein, Noise},
+    symplectic::{StormerVerlet, SymplecticEuler, Yoshida4},
+    Derivative, Point, Tolerance,
+};
+
+/// A scheme for advancing a trajectory. Implementors move `current` forward
+/// by one step, updating both `x` and every component of `y`.
+///
+/// Fixed-step schemes advance `x` by exactly `step_size`; adaptive schemes
+/// may treat it as an initial guess and choose their own step.
+pub trait Stepper {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64);
+
+    /// The continuous extension of the last step, for methods that come
+    /// with their own. Dense output falls back to Hermite interpolation.
+    fn continuous(&self) -> Option<Continuous> {
+        None
+    }
+}
+
+/// Sets `out` to `y + sum(h * weight * k)` over the given stages.
+pub(crate) fn combine(out: &mut Vec<f64>, y: &[f64], h: f64, stages: &[(f64, &[f64])]) {
+    out.clear();
+    out.extend_from_slice(y);
+
+    for &(weight, k) in stages {
+        if weight != 0.0 {
+            for (out, k) in out.iter_mut().zip(k) {
+                *out += h * weight * k;
+            }
+        }
+    }
+}
+
+/// Explicit forward Euler, first order.
+#[derive(Debug, Default, Clone)]
+pub struct Euler {
+    k: Vec<f64>,
+}
+
+impl Stepper for Euler {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        self.k.resize(current.y.len(), 0.0);
+        derivative(current.x, &current.y, &mut self.k);
+
+        for (y, k) in current.y.iter_mut().zip(&self.k) {
+            *y += k * step_size;
+        }
+        current.x += step_size;
+    }
+}
+
+/// Heun's method (explicit trapezoidal rule), second order.
+#[derive(Debug, Default, Clone)]
+pub struct Heun {
+    k1: Vec<f64>,
+    k2: Vec<f64>,
+    tmp: Vec<f64>,
+}
+
+impl Stepper for Heun {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let Heun { k1, k2, tmp } = self;
+        let (x, h) = (current.x, step_size);
+        k1.resize(current.y.len(), 0.0);
+        k2.resize(current.y.len(), 0.0);
+
+        derivative(x, &current.y, k1);
+        combine(tmp, &current.y, h, &[(1.0, k1)]);
+        derivative(x + h, tmp, k2);
+
+        combine(tmp, &current.y, h, &[(0.5, k1), (0.5, k2)]);
+        std::mem::swap(&mut current.y, tmp);
+        current.x = x + h;
+    }
+}
+
+/// The explicit midpoint method, second order.
+#[derive(Debug, Default, Clone)]
+pub struct Midpoint {
+    k1: Vec<f64>,
+    k2: Vec<f64>,
+    tmp: Vec<f64>,
+}
+
+impl Stepper for Midpoint {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let Midpoint { k1, k2, tmp } = self;
+        let (x, h) = (current.x, step_size);
+        k1.resize(current.y.len(), 0.0);
+        k2.resize(current.y.len(), 0.0);
+
+        derivative(x, &current.y, k1);
+        combine(tmp, &current.y, h, &[(0.5, k1)]);
+        derivative(x + h / 2.0, tmp, k2);
+
+        combine(tmp, &current.y, h, &[(1.0, k2)]);
+        std::mem::swap(&mut current.y, tmp);
+        current.x = x + h;
+    }
+}
+
+/// Classical fourth-order Runge–Kutta.
+#[derive(Debug, Default, Clone)]
+pub struct RungeKutta4 {
+    k: [Vec<f64>; 4],
+    tmp: Vec<f64>,
+}
+
+impl Stepper for RungeKutta4 {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let RungeKutta4 {
+            k: [k1, k2, k3, k4],
+            tmp,
+        } = self;
+        let (x, h) = (current.x, step_size);
+        for k in [&mut *k1, &mut *k2, &mut *k3, &mut *k4] {
+            k.resize(current.y.len(), 0.0);
+        }
+
+        derivative(x, &current.y, k1);
+        combine(tmp, &current.y, h, &[(0.5, k1)]);
+        derivative(x + h / 2.0, tmp, k2);
+        combine(tmp, &current.y, h, &[(0.5, k2)]);
+        derivative(x + h / 2.0, tmp, k3);
+        combine(tmp, &current.y, h, &[(1.0, k3)]);
+        derivative(x + h, tmp, k4);
+
+        combine(
+            tmp,
+            &current.y,
+            h,
+            &[
+                (1.0 / 6.0, k1),
+                (1.0 / 3.0, k2),
+                (1.0 / 3.0, k3),
+                (1.0 / 6.0, k4),
+            ],
+        );
+        std::mem::swap(&mut current.y, tmp);
+        current.x = x + h;
+    }
+}
+
+/// Selects one of the built-in steppers at runtime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Euler,
+    Heun,
+    Midpoint,
+    RungeKutta4,
+    DormandPrince(Tolerance),
+    /// Adams–Bashforth–Moulton of the given order.
+    AdamsBashforthMoulton(usize),
+    SymplecticEuler,
+    StormerVerlet,
+    Yoshida4,
+    EulerMaruyama,
+    Milstein,
+    BackwardEuler(Tolerance),
+    CrankNicolson(Tolerance),
+    /// Variable-order backward differentiation formulas, up to the given
+    /// order.
+    Bdf(usize, Tolerance),
+}
+
+impl Method {
+    /// Names accepted by [`Method::from_name`], as used on the command line
+    /// and in scenario files.
+    pub const NAMES: &'static [&'static str] = &[
+        "euler",
+        "heun",
+        "midpoint",
+        "rk4",
+        "rk45",
+        "abm1",
+        "abm2",
+        "abm3",
+        "abm4",
+        "abm5",
+        "symplectic-euler",
+        "verlet",
+        "yoshida4",
+        "euler-maruyama",
+        "milstein",
+        "backward-euler",
+        "crank-nicolson",
+        "bdf1",
+        "bdf2",
+        "bdf3",
+        "bdf4",
+        "bdf5",
+    ];
+
+    /// Looks up a method by name. `tolerance` only applies to adaptive
+    /// methods and to the Newton iterations of implicit ones.
+    pub fn from_name(name: &str, tolerance: Tolerance) -> Option<Self> {
+        match name {
+            "euler" => Some(Method::Euler),
+            "heun" => Some(Method::Heun),
+            "midpoint" => Some(Method::Midpoint),
+            "rk4" => Some(Method::RungeKutta4),
+            "rk45" => Some(Method::DormandPrince(tolerance)),
+            "abm1" => Some(Method::AdamsBashforthMoulton(1)),
+            "abm2" => Some(Method::AdamsBashforthMoulton(2)),
+            "abm3" => Some(Method::AdamsBashforthMoulton(3)),
+            "abm4" => Some(Method::AdamsBashforthMoulton(4)),
+            "abm5" => Some(Method::AdamsBashforthMoulton(5)),
+            "symplectic-euler" => Some(Method::SymplecticEuler),
+            "verlet" => Some(Method::StormerVerlet),
+            "yoshida4" => Some(Method::Yoshida4),
+            "euler-maruyama" => Some(Method::EulerMaruyama),
+            "milstein" => Some(Method::Milstein),
+            "backward-euler" => Some(Method::BackwardEuler(tolerance)),
+            "crank-nicolson" => Some(Method::CrankNicolson(tolerance)),
+            "bdf1" => Some(Method::Bdf(1, tolerance)),
+            "bdf2" => Some(Method::Bdf(2, tolerance)),
+            "bdf3" => Some(Method::Bdf(3, tolerance)),
+            "bdf4" => Some(Method::Bdf(4, tolerance)),
+            "bdf5" => Some(Method::Bdf(5, tolerance)),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Method::Euler => "euler",
+            Method::Heun => "heun",
+            Method::Midpoint => "midpoint",
+            Method::RungeKutta4 => "rk4",
+            Method::DormandPrince(_) => "rk45",
+            Method::AdamsBashforthMoulton(1) => "abm1",
+            Method::AdamsBashforthMoulton(2) => "abm2",
+            Method::AdamsBashforthMoulton(3) => "abm3",
+            Method::AdamsBashforthMoulton(4) => "abm4",
+            Method::AdamsBashforthMoulton(_) => "abm5",
+            Method::SymplecticEuler => "symplectic-euler",
+            Method::StormerVerlet => "verlet",
+            Method::Yoshida4 => "yoshida4",
+            Method::EulerMaruyama => "euler-maruyama",
+            Method::Milstein => "milstein",
+            Method::BackwardEuler(_) => "backward-euler",
+            Method::CrankNicolson(_) => "crank-nicolson",
+            Method::Bdf(1, _) => "bdf1",
+            Method::Bdf(2, _) => "bdf2",
+            Method::Bdf(3, _) => "bdf3",
+            Method::Bdf(4, _) => "bdf4",
+            Method::Bdf(..) => "bdf5",
+        }
+    }
+
+    /// Whether each step solves an equation, using the Jacobian.
+    pub fn is_implicit(self) -> bool {
+        matches!(
+            self,
+            Method::BackwardEuler(_) | Method::CrankNicolson(_) | Method::Bdf(..)
+        )
+    }
+
+    /// Whether the method needs a separable Hamiltonian system, its state
+    /// holding the positions and then the momenta.
+    pub fn is_symplectic(self) -> bool {
+        matches!(
+            self,
+            Method::SymplecticEuler | Method::StormerVerlet | Method::Yoshida4
+        )
+    }
+
+    /// Whether the method solves a stochastic equation, driven by noise.
+    pub fn is_stochastic(self) -> bool {
+        matches!(self, Method::EulerMaruyama | Method::Milstein)
+    }
+
+    /// The stepper, with implicit methods estimating the Jacobian
+    /// numerically and stochastic methods without noise.
+    pub fn stepper(self) -> Box<dyn Stepper> {
+        self.stepper_with_jacobian(None)
+    }
+
+    /// The stepper, with implicit methods using `jacobian` when given.
+    /// Other methods ignore it.
+    pub fn stepper_with_jacobian(
+        self,
+        jacobian: Option<Arc<JacobianFunction<'static>>>,
+    ) -> Box<dyn Stepper> {
+        match self {
+            Method::Euler => Box::new(Euler::default()),
+            Method::Heun => Box::new(Heun::default()),
+            Method::Midpoint => Box::new(Midpoint::default()),
+            Method::RungeKutta4 => Box::new(RungeKutta4::default()),
+            Method::DormandPrince(tolerance) => Box::new(DormandPrince::new(tolerance)),
+            Method::AdamsBashforthMoulton(order) => Box::new(AdamsBashforthMoulton::new(order)),
+            Method::SymplecticEuler => Box::new(SymplecticEuler::default()),
+            Method::StormerVerlet => Box::new(StormerVerlet::default()),
+            Method::Yoshida4 => Box::new(Yoshida4::default()),
+            Method::EulerMaruyama | Method::Milstein => {
+                self.stepper_with_noise(Noise::new(Arc::new(|_, _, g| g.fill(0.0)), 0, 0))
+            }
+            Method::BackwardEuler(tolerance) => Box::new(BackwardEuler::new(tolerance, jacobian)),
+            Method::CrankNicolson(tolerance) => Box::new(CrankNicolson::new(tolerance, jacobian)),
+            Method::Bdf(order, tolerance) => Box::new(Bdf::new(order, tolerance, jacobian)),
+        }
+    }
+
+    /// The stepper, with stochastic methods driven by `noise`. Other
+    /// methods ignore it.
+    pub fn stepper_with_noise(self, noise: Noise) -> Box<dyn Stepper> {
+        match self {
+            Method::EulerMaruyama => Box::new(EulerMaruyama::new(noise)),
+            Method::Milstein => Box::new(Milstein::new(noise)),
+            _ => self.stepper(),
+        }
+    }
+}
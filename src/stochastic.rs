@@ -0,0 +1,300 @@
+//! Stochastic differential equations `dY = f(x, Y) dx + g(x, Y) dW`, with
+//! diagonal noise: every component is driven by its own Wiener process,
+//! scaled by the diffusion `g` of that component.
+//!
+//! Each trajectory draws its noise from a generator seeded from the seed of
+//! the run and the index of the trajectory, so a run gives the same paths
+//! however its trajectories are spread over threads.
+
+use std::sync::Arc;
+
+use rand::{Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use rand_pcg::Pcg64;
+
+use crate::{Derivative, Point, Stepper};
+
+/// The diffusion `g(x, y)` of each component, written into the last
+/// argument like a [`Derivative`].
+pub type DiffusionFunction<'a> = dyn Fn(f64, &[f64], &mut [f64]) + Send + Sync + 'a;
+
+/// The noise driving one trajectory.
+#[derive(Clone)]
+pub struct Noise {
+    pub diffusion: Arc<DiffusionFunction<'static>>,
+    rng: Pcg64,
+}
+
+impl Noise {
+    /// The noise of trajectory `index` of a run seeded with `seed`.
+    pub fn new(diffusion: Arc<DiffusionFunction<'static>>, seed: u64, index: usize) -> Self {
+        // spread the indices over the seeds, so that neighbouring seeds do
+        // not share trajectories
+        let seed = seed ^ (index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        Noise {
+            diffusion,
+            rng: Pcg64::seed_from_u64(seed),
+        }
+    }
+
+    /// Fills `dw` with the increments of the Wiener processes over a step
+    /// of `step_size`, which must be positive.
+    fn increments(&mut self, step_size: f64, dw: &mut [f64]) {
+        let scale = step_size.sqrt();
+        for dw in dw {
+            *dw = scale * self.rng.sample::<f64, _>(StandardNormal);
+        }
+    }
+}
+
+/// Euler–Maruyama, of strong order 1/2 and weak order 1.
+#[derive(Clone)]
+pub struct EulerMaruyama {
+    noise: Noise,
+    k: Vec<f64>,
+    g: Vec<f64>,
+    dw: Vec<f64>,
+}
+
+impl EulerMaruyama {
+    pub fn new(noise: Noise) -> Self {
+        EulerMaruyama {
+            noise,
+            k: vec![],
+            g: vec![],
+            dw: vec![],
+        }
+    }
+}
+
+impl Stepper for EulerMaruyama {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let (x, h, n) = (current.x, step_size, current.y.len());
+        for buffer in [&mut self.k, &mut self.g, &mut self.dw] {
+            buffer.resize(n, 0.0);
+        }
+
+        derivative(x, &current.y, &mut self.k);
+        (self.noise.diffusion)(x, &current.y, &mut self.g);
+        self.noise.increments(h, &mut self.dw);
+
+        for i in 0..n {
+            current.y[i] += self.k[i] * h + self.g[i] * self.dw[i];
+        }
+        current.x = x + h;
+    }
+}
+
+/// Milstein's method for diagonal noise, of strong order 1. It corrects
+/// Euler–Maruyama with the derivative of each diffusion with respect to its
+/// own component, estimated by central differences.
+#[derive(Clone)]
+pub struct Milstein {
+    noise: Noise,
+    k: Vec<f64>,
+    g: Vec<f64>,
+    dw: Vec<f64>,
+    shifted: Vec<f64>,
+    g_shifted: [Vec<f64>; 2],
+}
+
+impl Milstein {
+    pub fn new(noise: Noise) -> Self {
+        Milstein {
+            noise,
+            k: vec![],
+            g: vec![],
+            dw: vec![],
+            shifted: vec![],
+            g_shifted: [vec![], vec![]],
+        }
+    }
+}
+
+impl Stepper for Milstein {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let (x, h, n) = (current.x, step_size, current.y.len());
+        let [below, above] = &mut self.g_shifted;
+        for buffer in [&mut self.k, &mut self.g, &mut self.dw, below, above] {
+            buffer.resize(n, 0.0);
+        }
+
+        derivative(x, &current.y, &mut self.k);
+        (self.noise.diffusion)(x, &current.y, &mut self.g);
+        self.noise.increments(h, &mut self.dw);
+
+        let diffusion = &self.noise.diffusion;
+        self.shifted.clone_from(&current.y);
+        for i in 0..n {
+            let delta = 1e-6 * (1.0 + current.y[i].abs());
+            self.shifted[i] = current.y[i] - delta;
+            diffusion(x, &self.shifted, below);
+            self.shifted[i] = current.y[i] + delta;
+            diffusion(x, &self.shifted, above);
+            self.shifted[i] = current.y[i];
+
+            let slope = (above[i] - below[i]) / (2.0 * delta);
+            let correction = 0.5 * self.g[i] * slope * (self.dw[i] * self.dw[i] - h);
+            self.k[i] = self.k[i] * h + self.g[i] * self.dw[i] + correction;
+        }
+
+        for (y, dy) in current.y.iter_mut().zip(&self.k) {
+            *y += dy;
+        }
+        current.x = x + h;
+    }
+}
+
+/// Statistics of an ensemble of paths at one value of x.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Band {
+    pub x: f64,
+    pub mean: Vec<f64>,
+    pub lower: Vec<f64>,
+    pub upper: Vec<f64>,
+}
+
+/// The mean and the `lower` and `upper` percentiles of every component
+/// across `paths`, step by step, which suits paths taken with the same fixed
+/// step. Each band covers the paths that reach that far, and takes its x
+/// from the first of them.
+pub fn ensemble(paths: &[Vec<Point>], lower: f64, upper: f64) -> Vec<Band> {
+    let longest = paths.iter().map(Vec::len).max().unwrap_or(0);
+
+    (0..longest)
+        .map(|step| {
+            let points: Vec<&Point> = paths.iter().filter_map(|path| path.get(step)).collect();
+            let dimension = points[0].y.len();
+            let mut band = Band {
+                x: points[0].x,
+                mean: Vec::with_capacity(dimension),
+                lower: Vec::with_capacity(dimension),
+                upper: Vec::with_capacity(dimension),
+            };
+
+            for i in 0..dimension {
+                let mut values: Vec<f64> = points.iter().map(|point| point.y[i]).collect();
+                values.sort_by(f64::total_cmp);
+                band.mean
+                    .push(values.iter().sum::<f64>() / values.len() as f64);
+                band.lower.push(percentile(&values, lower));
+                band.upper.push(percentile(&values, upper));
+            }
+            band
+        })
+        .collect()
+}
+
+/// The `p`th percentile of sorted `values`, interpolating linearly between
+/// neighbouring values.
+fn percentile(values: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (values.len() - 1) as f64;
+    let (below, fraction) = (rank.floor() as usize, rank.fract());
+    match values.get(below + 1) {
+        Some(next) => values[below] + fraction * (next - values[below]),
+        None => values[below],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{integrate, scalar, EndCondition};
+
+    const DRIFT: f64 = 0.5;
+    const VOLATILITY: f64 = 0.6;
+
+    /// Geometric Brownian motion, dY = 0.5 Y dx + 0.6 Y dW.
+    fn geometric() -> Arc<DiffusionFunction<'static>> {
+        Arc::new(|_, y, g| g[0] = VOLATILITY * y[0])
+    }
+
+    fn path(stepper: &mut dyn Stepper, step_size: f64) -> Vec<Point> {
+        let end_condition = EndCondition::until(1.0);
+        integrate(
+            (0.0, 1.0).into(),
+            step_size,
+            stepper,
+            end_condition,
+            &[],
+            scalar(|_, y| DRIFT * y),
+        )
+        .points
+    }
+
+    #[test]
+    fn reproducible_paths() {
+        let run = |seed, index| {
+            path(
+                &mut EulerMaruyama::new(Noise::new(geometric(), seed, index)),
+                0.01,
+            )
+        };
+
+        assert_eq!(run(7, 0), run(7, 0));
+        assert_ne!(run(7, 0), run(7, 1));
+        assert_ne!(run(7, 1), run(8, 0));
+    }
+
+    #[test]
+    fn mean_of_geometric_brownian_motion() {
+        let paths: Vec<Vec<Point>> = (0..8000)
+            .map(|i| path(&mut EulerMaruyama::new(Noise::new(geometric(), 1, i)), 0.01))
+            .collect();
+        let bands = ensemble(&paths, 5.0, 95.0);
+        let last = bands.last().unwrap();
+
+        // E[Y(x)] = exp(0.5 x); the sample mean has a spread of about 0.012
+        assert!((last.x - 1.0).abs() < 1e-9);
+        assert!(
+            (last.mean[0] - DRIFT.exp()).abs() < 0.05,
+            "{}",
+            last.mean[0]
+        );
+        // Y(1) is log-normal, with its 5th and 95th percentiles here
+        let quantile = |z: f64| (DRIFT - VOLATILITY.powi(2) / 2.0 + VOLATILITY * z).exp();
+        assert!((last.lower[0] - quantile(-1.645)).abs() < 0.05);
+        assert!((last.upper[0] - quantile(1.645)).abs() < 0.15);
+    }
+
+    #[test]
+    fn milstein_follows_the_path_closer() {
+        // the exact solution along the Brownian path the noise draws, which
+        // a second generator with the same seed replays
+        let exact = |seed: u64, index: usize, step_size: f64, steps: usize| {
+            let mut replay = Noise::new(geometric(), seed, index);
+            let mut dw = [0.0];
+            let w: f64 = (0..steps)
+                .map(|_| {
+                    replay.increments(step_size, &mut dw);
+                    dw[0]
+                })
+                .sum();
+            let x = step_size * steps as f64;
+            ((DRIFT - VOLATILITY.powi(2) / 2.0) * x + VOLATILITY * w).exp()
+        };
+
+        let (mut maruyama, mut milstein) = (0.0, 0.0);
+        for i in 0..200 {
+            let noise = || Noise::new(geometric(), 3, i);
+            let points = path(&mut EulerMaruyama::new(noise()), 0.01);
+            let end = &points[100];
+            maruyama += (end.y[0] - exact(3, i, 0.01, 100)).abs();
+
+            let points = path(&mut Milstein::new(noise()), 0.01);
+            milstein += (points[100].y[0] - exact(3, i, 0.01, 100)).abs();
+        }
+
+        assert!(milstein < maruyama / 3.0, "{milstein} vs {maruyama}");
+    }
+
+    #[test]
+    fn percentiles_interpolate() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(percentile(&values, 0.0), 1.0);
+        assert_eq!(percentile(&values, 50.0), 3.0);
+        assert_eq!(percentile(&values, 62.5), 3.5);
+        assert_eq!(percentile(&values, 100.0), 5.0);
+        assert_eq!(percentile(&[2.0], 90.0), 2.0);
+    }
+}
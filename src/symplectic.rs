@@ -0,0 +1,203 @@
+//! Symplectic methods for separable Hamiltonian systems, which keep the
+//! error in the energy bounded over long integrations instead of letting it
+//! drift.
+//!
+//! The state holds the positions `q` in its first half and the momenta `p`
+//! in its second, so a system of `n` degrees of freedom has `2n` equations.
+//! The methods rely on the derivative of the positions depending only on
+//! the momenta and that of the momenta only on the positions, as for
+//! `H(q, p) = T(p) + V(q)`; [`separable`] builds such a derivative from the
+//! two halves. A state of odd length has no such split and ends the
+//! trajectory as degenerate.
+
+use crate::{Derivative, Point, Stepper};
+
+/// The derivative of a separable system from the derivative of the
+/// positions, a function of the momenta, and that of the momenta, a
+/// function of the positions.
+pub fn separable(
+    velocity: impl Fn(f64, &[f64], &mut [f64]),
+    force: impl Fn(f64, &[f64], &mut [f64]),
+) -> impl Fn(f64, &[f64], &mut [f64]) {
+    move |x, y, dy| {
+        let n = y.len() / 2;
+        let (q, p) = y.split_at(n);
+        let (dq, dp) = dy.split_at_mut(n);
+        velocity(x, p, dq);
+        force(x, q, dp);
+    }
+}
+
+/// Moves the positions (`half` = 0) or the momenta (`half` = 1) of `y` by
+/// `h` times their derivative at `(x, y)`.
+fn update(derivative: &Derivative, x: f64, y: &mut [f64], h: f64, half: usize, k: &mut Vec<f64>) {
+    k.resize(y.len(), 0.0);
+    derivative(x, y, k);
+
+    let n = y.len() / 2;
+    let range = half * n..(half + 1) * n;
+    for (y, k) in y[range.clone()].iter_mut().zip(&k[range]) {
+        *y += h * k;
+    }
+}
+
+const POSITIONS: usize = 0;
+const MOMENTA: usize = 1;
+
+/// Symplectic Euler, first order: the momenta are updated first and the
+/// positions with the new momenta.
+#[derive(Debug, Default, Clone)]
+pub struct SymplecticEuler {
+    k: Vec<f64>,
+}
+
+impl Stepper for SymplecticEuler {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let (x, h) = (current.x, step_size);
+        if !current.y.len().is_multiple_of(2) {
+            current.y.fill(f64::NAN);
+        }
+
+        let (y, k) = (&mut current.y, &mut self.k);
+        update(derivative, x, y, h, MOMENTA, k);
+        update(derivative, x, y, h, POSITIONS, k);
+        current.x = x + h;
+    }
+}
+
+/// Störmer–Verlet, also known as leapfrog, second order and time
+/// reversible: half a step of the momenta, a full step of the positions
+/// and another half step of the momenta.
+#[derive(Debug, Default, Clone)]
+pub struct StormerVerlet {
+    k: Vec<f64>,
+}
+
+impl Stepper for StormerVerlet {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let (x, h) = (current.x, step_size);
+        if !current.y.len().is_multiple_of(2) {
+            current.y.fill(f64::NAN);
+        }
+
+        let (y, k) = (&mut current.y, &mut self.k);
+        update(derivative, x, y, h / 2.0, MOMENTA, k);
+        update(derivative, x + h / 2.0, y, h, POSITIONS, k);
+        update(derivative, x + h, y, h / 2.0, MOMENTA, k);
+        current.x = x + h;
+    }
+}
+
+/// Yoshida's fourth order method, three Störmer–Verlet steps of
+/// `w1 h`, `w0 h` and `w1 h`, the middle one backwards.
+#[derive(Debug, Default, Clone)]
+pub struct Yoshida4 {
+    verlet: StormerVerlet,
+}
+
+impl Stepper for Yoshida4 {
+    fn step(&mut self, derivative: &Derivative, current: &mut Point, step_size: f64) {
+        let cbrt2 = 2f64.cbrt();
+        let w1 = 1.0 / (2.0 - cbrt2);
+        let w0 = -cbrt2 * w1;
+
+        let x = current.x;
+        for w in [w1, w0, w1] {
+            self.verlet.step(derivative, current, w * step_size);
+        }
+        // the substeps add up to step_size up to rounding
+        current.x = x + step_size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{integrate, EndCondition, Euler, Method, Trajectory};
+
+    fn oscillator() -> impl Fn(f64, &[f64], &mut [f64]) {
+        separable(|_, p, dq| dq[0] = p[0], |_, q, dp| dp[0] = -q[0])
+    }
+
+    fn energy(point: &Point) -> f64 {
+        (point.y[0].powi(2) + point.y[1].powi(2)) / 2.0
+    }
+
+    fn run(stepper: &mut dyn Stepper, step_size: f64, max_x: f64) -> Trajectory {
+        let end_condition = EndCondition::until(max_x);
+        integrate(
+            (0.0, [1.0, 0.0]).into(),
+            step_size,
+            stepper,
+            end_condition,
+            &[],
+            oscillator(),
+        )
+    }
+
+    fn energy_drift(trajectory: &Trajectory) -> f64 {
+        trajectory
+            .points
+            .iter()
+            .map(|point| (energy(point) - 0.5).abs())
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn bounded_energy_error() {
+        // Euler gains energy at every step, a factor 1 + h^2 each time
+        let euler = energy_drift(&run(&mut Euler::default(), 0.1, 500.0));
+        assert!(euler > 1e6);
+
+        for (name, bound) in [
+            ("symplectic-euler", 0.03),
+            ("verlet", 2e-3),
+            ("yoshida4", 1e-4),
+        ] {
+            let method = Method::from_name(name, Default::default()).unwrap();
+            let short = energy_drift(&run(method.stepper().as_mut(), 0.1, 50.0));
+            let long = energy_drift(&run(method.stepper().as_mut(), 0.1, 500.0));
+            assert!(long < bound, "{name}: {long}");
+            // ten times as long, hardly any worse
+            assert!(long < 1.5 * short, "{name}: {short} then {long}");
+        }
+    }
+
+    #[test]
+    fn order_of_convergence() {
+        let error = |stepper: &mut dyn Stepper, step_size: f64| {
+            let trajectory = run(stepper, step_size, 4.0);
+            trajectory
+                .points
+                .iter()
+                .map(|p| (p.y[0] - p.x.cos()).abs().max((p.y[1] + p.x.sin()).abs()))
+                .fold(0.0, f64::max)
+        };
+        let ratio = |stepper: &mut dyn Stepper| error(stepper, 0.04) / error(stepper, 0.02);
+
+        assert!((ratio(&mut SymplecticEuler::default()) - 2.0).abs() < 0.2);
+        assert!((ratio(&mut StormerVerlet::default()) - 4.0).abs() < 0.4);
+        assert!((ratio(&mut Yoshida4::default()) - 16.0).abs() < 1.6);
+    }
+
+    #[test]
+    fn verlet_is_reversible() {
+        let pendulum = separable(|_, p, dq| dq[0] = p[0], |_, q, dp| dp[0] = -q[0].sin());
+        let start: Point = (0.0, [1.0, 0.5]).into();
+        let mut point = start.clone();
+        let mut stepper = StormerVerlet::default();
+        for _ in 0..100 {
+            stepper.step(&pendulum, &mut point, 0.1);
+        }
+        for _ in 0..100 {
+            stepper.step(&pendulum, &mut point, -0.1);
+        }
+
+        assert!((point.x - start.x).abs() < 1e-12);
+        assert!(point
+            .y
+            .iter()
+            .zip(&start.y)
+            .all(|(a, b)| (a - b).abs() < 1e-12));
+    }
+}
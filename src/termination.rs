@@ -0,0 +1,479 @@
+//! Conditions that end a trajectory.
+//!
+//! A [`Termination`] is checked after every step. Conditions on the state
+//! alone also give a boundary function, so that the integrator can locate
+//! the point within the step where the condition starts to hold and end the
+//! trajectory exactly there. Conditions combine with [`Any`] and [`All`].
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    event::{self, Action, Direction, Event, EventFunction},
+    Derivative, Point,
+};
+
+/// The state of a trajectory after a step.
+pub struct Progress<'a> {
+    /// The point before the step, which is `point` itself before the first.
+    pub previous: &'a Point,
+    pub point: &'a Point,
+    /// Steps taken since the start.
+    pub steps: usize,
+    pub derivative: &'a Derivative<'a>,
+}
+
+/// Why a condition holds.
+pub struct Reached {
+    /// Describes the condition, for reporting which one ended a trajectory.
+    pub reason: String,
+    /// For conditions on the state alone, a function of the state that is
+    /// negative before the condition holds and not negative once it does.
+    pub boundary: Option<Box<EventFunction<'static>>>,
+}
+
+impl Reached {
+    pub fn new(reason: impl fmt::Display) -> Self {
+        Reached {
+            reason: reason.to_string(),
+            boundary: None,
+        }
+    }
+
+    /// Reached on the boundary where `boundary` crosses zero.
+    pub fn on(
+        reason: impl fmt::Display,
+        boundary: impl Fn(f64, &[f64]) -> f64 + Send + Sync + 'static,
+    ) -> Self {
+        Reached {
+            reason: reason.to_string(),
+            boundary: Some(Box::new(boundary)),
+        }
+    }
+}
+
+pub trait Termination {
+    /// Prepares for a trajectory starting at `start`.
+    fn start(&mut self, _start: &Point) {}
+
+    /// Checks the state after a step, returning why the trajectory should
+    /// end there.
+    fn check(&mut self, progress: &Progress) -> Option<Reached>;
+}
+
+impl<T: Termination + ?Sized> Termination for &mut T {
+    fn start(&mut self, start: &Point) {
+        (**self).start(start);
+    }
+
+    fn check(&mut self, progress: &Progress) -> Option<Reached> {
+        (**self).check(progress)
+    }
+}
+
+impl<T: Termination + ?Sized> Termination for Box<T> {
+    fn start(&mut self, start: &Point) {
+        (**self).start(start);
+    }
+
+    fn check(&mut self, progress: &Progress) -> Option<Reached> {
+        (**self).check(progress)
+    }
+}
+
+/// Stops once `x` leaves `[min, max]`, which suits integration in either
+/// direction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XRange {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl Termination for XRange {
+    fn check(&mut self, progress: &Progress) -> Option<Reached> {
+        let x = progress.point.x;
+
+        if let Some(max) = self.max.filter(|&max| x >= max) {
+            return Some(Reached::on(format!("x reached {max}"), move |x, _| x - max));
+        }
+        if let Some(min) = self.min.filter(|&min| x <= min) {
+            return Some(Reached::on(format!("x reached {min}"), move |x, _| min - x));
+        }
+
+        None
+    }
+}
+
+/// Stops once any component of `y` leaves its bounds. Components beyond the
+/// length of `lower` and `upper` are unbounded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct YBox {
+    pub lower: Vec<f64>,
+    pub upper: Vec<f64>,
+}
+
+impl YBox {
+    /// The box `[-max_abs, max_abs]` in each of `dimension` components.
+    pub fn symmetric(max_abs: f64, dimension: usize) -> Self {
+        YBox {
+            lower: vec![-max_abs; dimension],
+            upper: vec![max_abs; dimension],
+        }
+    }
+
+    /// How far `y` lies outside the box, or minus how far inside it.
+    fn distance(lower: &[f64], upper: &[f64], y: &[f64]) -> f64 {
+        let below = lower.iter().zip(y).map(|(lower, y)| lower - y);
+        let above = upper.iter().zip(y).map(|(upper, y)| y - upper);
+        below.chain(above).fold(f64::NEG_INFINITY, f64::max)
+    }
+}
+
+impl Termination for YBox {
+    fn check(&mut self, progress: &Progress) -> Option<Reached> {
+        let y = &progress.point.y;
+        let outside = |i: usize| {
+            self.lower.get(i).is_some_and(|&lower| y[i] <= lower)
+                || self.upper.get(i).is_some_and(|&upper| y[i] >= upper)
+        };
+        let i = (0..y.len()).find(|&i| outside(i))?;
+
+        let (lower, upper) = (self.lower.clone(), self.upper.clone());
+        Some(Reached::on(
+            format!(
+                "y{i} reached the bounds [{}, {}]",
+                self.lower.get(i).unwrap_or(&f64::NEG_INFINITY),
+                self.upper.get(i).unwrap_or(&f64::INFINITY)
+            ),
+            move |_, y| YBox::distance(&lower, &upper, y),
+        ))
+    }
+}
+
+/// Stops after a number of steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxSteps(pub usize);
+
+impl Termination for MaxSteps {
+    fn check(&mut self, progress: &Progress) -> Option<Reached> {
+        (progress.steps >= self.0).then(|| Reached::new(format!("{} steps taken", self.0)))
+    }
+}
+
+/// Stops once integrating a trajectory has taken longer than a budget of
+/// wall-clock time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WallClock {
+    pub budget: Duration,
+    started: Option<Instant>,
+}
+
+impl WallClock {
+    pub fn new(budget: Duration) -> Self {
+        WallClock {
+            budget,
+            started: None,
+        }
+    }
+}
+
+impl Termination for WallClock {
+    fn start(&mut self, _: &Point) {
+        self.started = Some(Instant::now());
+    }
+
+    fn check(&mut self, _: &Progress) -> Option<Reached> {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        (started.elapsed() >= self.budget)
+            .then(|| Reached::new(format!("wall-clock budget of {:?} spent", self.budget)))
+    }
+}
+
+/// Stops once every component of `y'` has stayed below `epsilon` in
+/// magnitude for `steps` consecutive steps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SteadyState {
+    pub epsilon: f64,
+    pub steps: usize,
+    still: usize,
+    slope: Vec<f64>,
+}
+
+impl SteadyState {
+    pub fn new(epsilon: f64, steps: usize) -> Self {
+        SteadyState {
+            epsilon,
+            steps,
+            still: 0,
+            slope: vec![],
+        }
+    }
+}
+
+impl Termination for SteadyState {
+    fn start(&mut self, _: &Point) {
+        self.still = 0;
+    }
+
+    fn check(&mut self, progress: &Progress) -> Option<Reached> {
+        let Point { x, y } = progress.point;
+        self.slope.resize(y.len(), 0.0);
+        (progress.derivative)(*x, y, &mut self.slope);
+
+        if self.slope.iter().all(|dy| dy.abs() < self.epsilon) {
+            self.still += 1;
+        } else {
+            self.still = 0;
+        }
+
+        (self.still >= self.steps).then(|| {
+            Reached::new(format!(
+                "steady state, |y'| < {} for {} steps",
+                self.epsilon, self.steps
+            ))
+        })
+    }
+}
+
+/// Stops as soon as any of the conditions holds.
+pub struct Any(pub Vec<Box<dyn Termination + Send>>);
+
+impl Termination for Any {
+    fn start(&mut self, start: &Point) {
+        for condition in &mut self.0 {
+            condition.start(start);
+        }
+    }
+
+    fn check(&mut self, progress: &Progress) -> Option<Reached> {
+        // every condition sees every step, so that the stateful ones keep
+        // count
+        let reached: Vec<Reached> = self
+            .0
+            .iter_mut()
+            .filter_map(|condition| condition.check(progress))
+            .collect();
+        if reached.is_empty() {
+            return None;
+        }
+
+        // conditions without a boundary hold at the end of the step, after
+        // any boundary crossed within it
+        let mut reasons = vec![];
+        let mut events = vec![];
+        let mut unbounded = None;
+        for Reached { reason, boundary } in reached {
+            match boundary {
+                Some(function) => {
+                    reasons.push(reason);
+                    events.push(Event {
+                        function,
+                        direction: Direction::Both,
+                        action: Action::Terminate,
+                    });
+                }
+                None => {
+                    unbounded.get_or_insert(reason);
+                }
+            }
+        }
+        if events.is_empty() {
+            return unbounded.map(Reached::new);
+        }
+
+        // when several start to hold within one step, the reason is that of
+        // the boundary crossed first, and so is the end
+        let first = event::crossings(
+            &events.iter().collect::<Vec<_>>(),
+            progress.derivative,
+            progress.previous,
+            progress.point,
+        )
+        .first()
+        .map_or(0, |crossing| crossing.event);
+        let boundaries: Vec<_> = events.into_iter().map(|event| event.function).collect();
+        Some(Reached {
+            reason: reasons.swap_remove(first),
+            boundary: Some(Box::new(move |x: f64, y: &[f64]| {
+                boundaries
+                    .iter()
+                    .map(|boundary| boundary(x, y))
+                    .fold(f64::NEG_INFINITY, f64::max)
+            })),
+        })
+    }
+}
+
+/// Stops once all of the conditions hold at the same time.
+pub struct All(pub Vec<Box<dyn Termination + Send>>);
+
+impl Termination for All {
+    fn start(&mut self, start: &Point) {
+        for condition in &mut self.0 {
+            condition.start(start);
+        }
+    }
+
+    fn check(&mut self, progress: &Progress) -> Option<Reached> {
+        let reached: Vec<Option<Reached>> = self
+            .0
+            .iter_mut()
+            .map(|condition| condition.check(progress))
+            .collect();
+        let reached: Vec<Reached> = reached.into_iter().collect::<Option<_>>()?;
+
+        let reasons: Vec<&str> = reached.iter().map(|r| r.reason.as_str()).collect();
+        let reason = reasons.join(" and ");
+
+        // the conditions all hold where the least of the boundaries does
+        let boundaries: Option<Vec<_>> = reached.into_iter().map(|r| r.boundary).collect();
+        Some(Reached {
+            reason,
+            boundary: boundaries.map(|boundaries| {
+                Box::new(move |x: f64, y: &[f64]| {
+                    boundaries
+                        .iter()
+                        .map(|boundary| boundary(x, y))
+                        .fold(f64::INFINITY, f64::min)
+                }) as Box<EventFunction>
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{integrate, scalar, Euler, RungeKutta4, Stop, Trajectory};
+
+    fn exponential(rate: f64, termination: impl Termination) -> Trajectory {
+        integrate(
+            (0.0, 1.0).into(),
+            0.1,
+            &mut RungeKutta4::default(),
+            termination,
+            &[],
+            scalar(move |_, y| rate * y),
+        )
+    }
+
+    fn decay(termination: impl Termination) -> Trajectory {
+        exponential(-1.0, termination)
+    }
+
+    fn growth(termination: impl Termination) -> Trajectory {
+        exponential(1.0, termination)
+    }
+
+    fn reason(trajectory: &Trajectory) -> &str {
+        match &trajectory.stop {
+            Stop::Condition(reason) => reason,
+            other => panic!("stopped by {other}"),
+        }
+    }
+
+    #[test]
+    fn x_range_in_either_direction() {
+        let range = XRange {
+            min: Some(-1.0),
+            max: Some(2.0),
+        };
+        let forward = decay(range);
+        assert!((forward.points.last().unwrap().x - 2.0).abs() < 1e-12);
+        assert_eq!(reason(&forward), "x reached 2");
+
+        let backward = integrate(
+            (0.0, 1.0).into(),
+            -0.3,
+            &mut Euler::default(),
+            range,
+            &[],
+            scalar(|_, y| y),
+        );
+        assert!((backward.points.last().unwrap().x + 1.0).abs() < 1e-12);
+        assert_eq!(reason(&backward), "x reached -1");
+    }
+
+    #[test]
+    fn y_box_ends_on_its_boundary() {
+        let trajectory = decay(YBox {
+            lower: vec![0.25],
+            upper: vec![2.0],
+        });
+
+        let last = trajectory.points.last().unwrap();
+        assert!((last.y[0] - 0.25).abs() < 1e-12);
+        assert!((last.x - 4f64.ln()).abs() < 1e-5);
+        assert_eq!(reason(&trajectory), "y0 reached the bounds [0.25, 2]");
+    }
+
+    #[test]
+    fn max_steps() {
+        let trajectory = decay(MaxSteps(7));
+        assert_eq!(trajectory.points.len(), 8);
+        assert_eq!(reason(&trajectory), "7 steps taken");
+    }
+
+    #[test]
+    fn steady_state() {
+        // |y'| = exp(-x) falls below 1e-3 at x = ln(1000) = 6.9
+        let trajectory = decay(SteadyState::new(1e-3, 5));
+        let last = trajectory.points.last().unwrap();
+        assert!((last.x - 7.4).abs() < 1e-9, "{}", last.x);
+    }
+
+    #[test]
+    fn any_reports_the_condition_that_held() {
+        let trajectory = growth(Any(vec![
+            Box::new(XRange {
+                min: None,
+                max: Some(10.0),
+            }),
+            Box::new(YBox::symmetric(2.0, 1)),
+            Box::new(MaxSteps(1000)),
+        ]));
+
+        let last = trajectory.points.last().unwrap();
+        assert!((last.x - 2f64.ln()).abs() < 1e-5);
+        assert_eq!(reason(&trajectory), "y0 reached the bounds [-2, 2]");
+    }
+
+    #[test]
+    fn any_reports_the_boundary_crossed_first() {
+        // within the step from 0.6 to 0.7, y leaves the box at ln 2 before x
+        // reaches 0.695, and the step count is reached at its end
+        let trajectory = growth(Any(vec![
+            Box::new(MaxSteps(7)),
+            Box::new(XRange {
+                min: None,
+                max: Some(0.695),
+            }),
+            Box::new(YBox::symmetric(2.0, 1)),
+        ]));
+
+        let last = trajectory.points.last().unwrap();
+        assert!((last.x - 2f64.ln()).abs() < 1e-5, "{}", last.x);
+        assert_eq!(reason(&trajectory), "y0 reached the bounds [-2, 2]");
+    }
+
+    #[test]
+    fn all_waits_for_every_condition() {
+        let trajectory = growth(All(vec![
+            Box::new(XRange {
+                min: None,
+                max: Some(1.0),
+            }),
+            Box::new(YBox::symmetric(2.0, 1)),
+        ]));
+
+        // y leaves the box at ln 2, before x reaches 1
+        let last = trajectory.points.last().unwrap();
+        assert!((last.x - 1.0).abs() < 1e-12);
+        assert_eq!(
+            reason(&trajectory),
+            "x reached 1 and y0 reached the bounds [-2, 2]"
+        );
+    }
+}